@@ -0,0 +1,584 @@
+//! This module authenticates requests from the `Authorization: Bearer <token>` header.
+//!
+//! Tokens are verified locally instead of by calling Cognito on every request. In `cognito`
+//! mode the signature is checked against the user pool's JSON Web Key Set, which is fetched
+//! once and cached for `auth.jwks_ttl_secs` (and refetched early when a token names a key the
+//! cache does not hold, so key rotation is picked up). In `static` mode tokens are HS256-signed
+//! with `auth.static_secret`, which lets tests and local runs mint their own tokens.
+//!
+//! In both modes a token is accepted only if its signature is valid, it has not expired, its
+//! `iss` matches the user pool (or `auth.static_issuer`), its `token_use` is `access` and its
+//! `client_id` matches `auth.client_id`.
+//!
+//! # Roles
+//!
+//! A user's roles come from the Cognito groups in the token's `cognito:groups` claim:
+//!
+//! | Group         | Role               | Grants                                |
+//! |---------------|--------------------|---------------------------------------|
+//! | `editor`      | `Role::Editor`     | Uploading and editing issues          |
+//! | `news-editor` | `Role::NewsEditor` | Managing news items                   |
+//! | `admin`       | `Role::Admin`      | Deleting issues, and every other role |
+//!
+//! Groups with other names are ignored. Routes declare the role they need by taking an
+//! `Authorized<R>` extractor, where `R` is one of the marker types `Editor`, `NewsEditor` or `Admin`.
+//!
+//! # Structs
+//!
+//! - `TokenVerifier`: Verifies tokens and caches the signing keys.
+//! - `AuthenticatedUser`: The verified caller. Used as an extractor, it rejects the request with
+//!   `401 Unauthorized` unless it carries a valid token.
+//! - `Authorized<R>`: An `AuthenticatedUser` holding role `R`. Used as an extractor, it also
+//!   rejects the request with `403 Forbidden` if the role is missing.
+//!
+//! # Example
+//!
+//! ```
+//! #[actix_web::delete("/issues/{issue_number}")]
+//! async fn delete_issue(user: Authorized<Admin>) -> Result<HttpResponse, ApiError> {
+//!     log::info!("{} is deleting an issue", user.username);
+//!     // ...
+//! }
+//! ```
+
+use std::{
+    marker::PhantomData,
+    ops::Deref,
+    time::{Duration, Instant},
+};
+
+use actix_web::{dev::Payload, http::header, web::Data, FromRequest, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{AuthConfig, AuthMode},
+    error::ApiError,
+    state::AppState,
+};
+
+/// The shortest interval between JWKS fetches triggered by an unknown key id, so tokens with
+/// made-up `kid`s cannot make the server hammer Cognito.
+const MIN_JWKS_REFETCH: Duration = Duration::from_secs(30);
+
+/// How long a JWKS fetch may take to connect, and to complete. Requests that need a fetch wait
+/// on it, so an unresponsive Cognito must not hold them open indefinitely.
+const JWKS_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing bearer token")]
+    MissingToken,
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+    #[error("Could not fetch signing keys: {0}")]
+    Jwks(String),
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::MissingToken | AuthError::InvalidToken(_) => {
+                ApiError::Unauthorized(e.to_string())
+            }
+            AuthError::Jwks(_) => ApiError::Upstream(e.to_string()),
+        }
+    }
+}
+
+/// The claims this server reads from a Cognito access token.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct Claims {
+    sub: String,
+    username: Option<String>,
+    token_use: String,
+    client_id: String,
+    #[serde(rename = "cognito:groups", default)]
+    groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Editor,
+    NewsEditor,
+    Admin,
+}
+
+impl Role {
+    /// The Cognito group that grants this role.
+    pub fn group(self) -> &'static str {
+        match self {
+            Role::Editor => "editor",
+            Role::NewsEditor => "news-editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    fn from_group(group: &str) -> Option<Role> {
+        match group {
+            "editor" => Some(Role::Editor),
+            "news-editor" => Some(Role::NewsEditor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// A caller whose bearer token has been verified.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub sub: String,
+    pub username: String,
+    pub roles: Vec<Role>,
+}
+
+impl AuthenticatedUser {
+    /// Whether the user holds `role`, either directly or by being an admin.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&Role::Admin) || self.roles.contains(&role)
+    }
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+enum KeySource {
+    Jwks {
+        url: String,
+        ttl: Duration,
+        http: reqwest::Client,
+        cache: RwLock<Option<CachedKeys>>,
+    },
+    Static(DecodingKey),
+}
+
+pub struct TokenVerifier {
+    source: KeySource,
+    issuer: String,
+    client_id: String,
+}
+
+impl TokenVerifier {
+    pub fn new(config: &AuthConfig) -> Result<Self, AuthError> {
+        let source = match config.mode {
+            AuthMode::Cognito => KeySource::Jwks {
+                url: config.jwks_url(),
+                ttl: config.jwks_ttl(),
+                http: reqwest::Client::builder()
+                    .connect_timeout(JWKS_CONNECT_TIMEOUT)
+                    .timeout(JWKS_TIMEOUT)
+                    .build()
+                    .map_err(|e| AuthError::Jwks(e.to_string()))?,
+                cache: RwLock::new(None),
+            },
+            AuthMode::Static => KeySource::Static(DecodingKey::from_secret(
+                config
+                    .static_secret
+                    .as_deref()
+                    .unwrap_or_default()
+                    .as_bytes(),
+            )),
+        };
+        Ok(TokenVerifier {
+            source,
+            issuer: config.issuer(),
+            client_id: config.client_id.clone(),
+        })
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<AuthenticatedUser, AuthError> {
+        let (key, algorithm) = match &self.source {
+            KeySource::Static(key) => (key.clone(), Algorithm::HS256),
+            KeySource::Jwks { .. } => {
+                let header = jsonwebtoken::decode_header(token)
+                    .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+                let kid = header
+                    .kid
+                    .ok_or_else(|| AuthError::InvalidToken("token has no key id".to_string()))?;
+                (self.jwks_key(&kid).await?, Algorithm::RS256)
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        // Cognito access tokens carry `client_id` rather than `aud`
+        validation.validate_aud = false;
+        let claims = jsonwebtoken::decode::<Claims>(token, &key, &validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+
+        if claims.token_use != "access" {
+            return Err(AuthError::InvalidToken(format!(
+                "expected an access token, got token_use {:?}",
+                claims.token_use
+            )));
+        }
+        if claims.client_id != self.client_id {
+            return Err(AuthError::InvalidToken(
+                "token was issued to a different client".to_string(),
+            ));
+        }
+        Ok(AuthenticatedUser {
+            username: claims.username.unwrap_or_else(|| claims.sub.clone()),
+            sub: claims.sub,
+            roles: claims
+                .groups
+                .iter()
+                .filter_map(|group| Role::from_group(group))
+                .collect(),
+        })
+    }
+
+    /// Finds the signing key named `kid`, refetching the key set if it is stale or lacks `kid`.
+    async fn jwks_key(&self, kid: &str) -> Result<DecodingKey, AuthError> {
+        let KeySource::Jwks {
+            url,
+            ttl,
+            http,
+            cache,
+        } = &self.source
+        else {
+            return Err(AuthError::Jwks(
+                "signing keys are only fetched in cognito mode".to_string(),
+            ));
+        };
+
+        let refetch = match cache.read().await.as_ref() {
+            Some(cached) if cached.fetched_at.elapsed() < *ttl => {
+                if let Some(jwk) = cached.keys.find(kid) {
+                    return decoding_key(jwk);
+                }
+                cached.fetched_at.elapsed() >= MIN_JWKS_REFETCH
+            }
+            _ => true,
+        };
+        if !refetch {
+            return Err(AuthError::InvalidToken(format!("unknown key id {:?}", kid)));
+        }
+
+        let body = http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AuthError::Jwks(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| AuthError::Jwks(e.to_string()))?;
+        let keys: JwkSet =
+            serde_json::from_slice(&body).map_err(|e| AuthError::Jwks(e.to_string()))?;
+        let key = keys.find(kid).map(decoding_key);
+        *cache.write().await = Some(CachedKeys {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        key.unwrap_or_else(|| Err(AuthError::InvalidToken(format!("unknown key id {:?}", kid))))
+    }
+}
+
+fn decoding_key(jwk: &jsonwebtoken::jwk::Jwk) -> Result<DecodingKey, AuthError> {
+    DecodingKey::from_jwk(jwk).map_err(|e| AuthError::Jwks(e.to_string()))
+}
+
+/// Reads the token from an `Authorization: Bearer <token>` header.
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let state = req.app_data::<Data<AppState>>().cloned();
+        let token = bearer_token(req).map(str::to_string);
+        Box::pin(async move {
+            let state = state
+                .ok_or_else(|| ApiError::Internal("AppState is not registered".to_string()))?;
+            let token = token.ok_or(AuthError::MissingToken)?;
+            Ok(state.auth.verify(&token).await?)
+        })
+    }
+}
+
+/// A role a route can require through `Authorized<R>`.
+pub trait RequiredRole {
+    const ROLE: Role;
+}
+
+/// Requires `Role::Editor`.
+#[derive(Debug, Clone, Copy)]
+pub struct Editor;
+
+impl RequiredRole for Editor {
+    const ROLE: Role = Role::Editor;
+}
+
+/// Requires `Role::NewsEditor`.
+#[derive(Debug, Clone, Copy)]
+pub struct NewsEditor;
+
+impl RequiredRole for NewsEditor {
+    const ROLE: Role = Role::NewsEditor;
+}
+
+/// Requires `Role::Admin`.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// An authenticated user who holds the role `R`.
+#[derive(Debug, Clone)]
+pub struct Authorized<R: RequiredRole> {
+    user: AuthenticatedUser,
+    role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> Deref for Authorized<R> {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<R: RequiredRole + 'static> FromRequest for Authorized<R> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        AuthenticatedUser::from_request(req, payload)
+            .map(|user| {
+                let user = user?;
+                if !user.has_role(R::ROLE) {
+                    return Err(ApiError::Forbidden(format!(
+                        "This action requires the `{}` role, which {} does not have",
+                        R::ROLE.group(),
+                        user.username
+                    )));
+                }
+                Ok(Authorized {
+                    user,
+                    role: PhantomData,
+                })
+            })
+            .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::{app_state, auth_config, claims, sign, token, SECRET};
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn with(mut claims: Value, field: &str, value: Value) -> Value {
+        claims[field] = value;
+        claims
+    }
+
+    async fn verify(claims: &Value) -> Result<AuthenticatedUser, AuthError> {
+        TokenVerifier::new(&auth_config())
+            .unwrap()
+            .verify(&sign(claims, SECRET))
+            .await
+    }
+
+    #[actix_web::test]
+    async fn accepts_a_valid_token() {
+        let user = verify(&claims(&["editor"])).await.unwrap();
+        assert_eq!(user.sub, "user-1");
+        assert_eq!(user.username, "ann");
+        assert_eq!(user.roles, [Role::Editor]);
+    }
+
+    #[actix_web::test]
+    async fn username_defaults_to_sub() {
+        let mut claims = claims(&[]);
+        claims.as_object_mut().unwrap().remove("username");
+        assert_eq!(verify(&claims).await.unwrap().username, "user-1");
+    }
+
+    #[actix_web::test]
+    async fn rejects_an_expired_token() {
+        // Well past jsonwebtoken's default leeway of a minute
+        let expired = with(claims(&[]), "exp", json!(now() - 600));
+        assert!(matches!(
+            verify(&expired).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_a_token_without_expiry() {
+        let mut claims = claims(&[]);
+        claims.as_object_mut().unwrap().remove("exp");
+        assert!(matches!(
+            verify(&claims).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_another_issuer() {
+        let claims = with(
+            claims(&[]),
+            "iss",
+            json!("https://cognito-idp.example.com/pool"),
+        );
+        assert!(matches!(
+            verify(&claims).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_id_tokens() {
+        let claims = with(claims(&[]), "token_use", json!("id"));
+        assert!(matches!(
+            verify(&claims).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_another_client() {
+        let claims = with(claims(&[]), "client_id", json!("other-client"));
+        assert!(matches!(
+            verify(&claims).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_a_bad_signature() {
+        let verifier = TokenVerifier::new(&auth_config()).unwrap();
+        let forged = sign(&claims(&["admin"]), "not-the-secret");
+        assert!(matches!(
+            verifier.verify(&forged).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            verifier.verify("not-a-jwt").await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn maps_groups_to_roles() {
+        let user = verify(&claims(&["news-editor", "readers", "editor", "Admin"]))
+            .await
+            .unwrap();
+        // Group names are matched exactly; unknown groups are ignored
+        assert_eq!(user.roles, [Role::NewsEditor, Role::Editor]);
+        assert!(user.has_role(Role::Editor));
+        assert!(user.has_role(Role::NewsEditor));
+        assert!(!user.has_role(Role::Admin));
+
+        let mut claims = claims(&[]);
+        claims.as_object_mut().unwrap().remove("cognito:groups");
+        assert!(verify(&claims).await.unwrap().roles.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn admin_holds_every_role() {
+        let admin = verify(&claims(&["admin"])).await.unwrap();
+        for role in [Role::Editor, Role::NewsEditor, Role::Admin] {
+            assert!(admin.has_role(role));
+        }
+    }
+
+    async fn editor_only(user: Authorized<Editor>) -> HttpResponse {
+        HttpResponse::Ok().body(user.username.clone())
+    }
+
+    async fn admin_only(_: Authorized<Admin>) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn status(path: &str, token: Option<&str>) -> StatusCode {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(app_state(
+                    &std::env::temp_dir().join("nnm-auth-tests"),
+                )))
+                .route("/editor", web::get().to(editor_only))
+                .route("/admin", web::get().to(admin_only)),
+        )
+        .await;
+        let mut request = test::TestRequest::get().uri(path);
+        if let Some(token) = token {
+            request = request.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+        }
+        test::call_service(&app, request.to_request())
+            .await
+            .status()
+    }
+
+    #[actix_web::test]
+    async fn missing_or_invalid_tokens_are_unauthorized() {
+        assert_eq!(status("/editor", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status("/editor", Some("garbage")).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let expired = sign(&with(claims(&["admin"]), "exp", json!(now() - 600)), SECRET);
+        assert_eq!(
+            status("/admin", Some(&expired)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn a_missing_role_is_forbidden() {
+        assert_eq!(
+            status("/editor", Some(&token(&[]))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("/editor", Some(&token(&["news-editor"]))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("/admin", Some(&token(&["editor"]))).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_web::test]
+    async fn a_held_role_is_authorized() {
+        assert_eq!(
+            status("/editor", Some(&token(&["editor"]))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/editor", Some(&token(&["admin"]))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/admin", Some(&token(&["admin"]))).await,
+            StatusCode::OK
+        );
+    }
+}
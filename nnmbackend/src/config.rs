@@ -0,0 +1,1056 @@
+//! This module defines the server configuration.
+//!
+//! Settings are read from a TOML file and then overridden by environment variables, so a
+//! checked-in file can hold the defaults for an environment while secrets come from the
+//! process environment. The result is validated once at startup; any problem is reported
+//! with the offending setting named, instead of surfacing later as a panic inside a request.
+//!
+//! # Loading
+//!
+//! The file is read from `NNM_CONFIG`, or from `nnm.toml` in the working directory if that
+//! variable is unset. A missing default file is not an error; every setting has a default
+//! except the Shopify store domain and Storefront key.
+//!
+//! # Environment overrides
+//!
+//! | Variable                  | Setting                      |
+//! |---------------------------|------------------------------|
+//! | `NNM_BIND_ADDRESS`        | `server.bind_address`        |
+//! | `NNM_STORAGE_BACKEND`     | `storage.backend`            |
+//! | `NNM_S3_BUCKETS`          | `storage.buckets` (`region=bucket,...`) |
+//! | `NNM_PRESIGN_TTL_SECS`    | `storage.presign_ttl_secs`   |
+//! | `NNM_MULTIPART_PART_SIZE_MIB` | `storage.multipart_part_size_mib` |
+//! | `NNM_MAX_UPLOAD_MIB`      | `storage.max_upload_mib`     |
+//! | `NNM_LOCAL_STORAGE_DIR`   | `storage.local.root`         |
+//! | `NNM_PUBLIC_URL`          | `storage.local.public_url`   |
+//! | `NNM_BLOB_SIGNING_SECRET` | `storage.local.signing_secret` |
+//! | `NNM_METADATA_BACKEND`    | `metadata.backend`           |
+//! | `NNM_ISSUE_TABLE`         | `metadata.issue_table`       |
+//! | `NNM_NEWS_TABLE`          | `metadata.news_table`        |
+//! | `NNM_NEWS_INDEX`          | `metadata.news_index`        |
+//! | `NNM_SQLITE_PATH`         | `metadata.sqlite_path`       |
+//! | `NNM_ISSUE_CACHE_TTL_SECS` | `cache.issue_ttl_secs`      |
+//! | `NNM_PRODUCT_CACHE_TTL_SECS` | `cache.product_ttl_secs`  |
+//! | `NNM_AUTH_MODE`           | `auth.mode`                  |
+//! | `NNM_COGNITO_REGION`      | `auth.region`                |
+//! | `NNM_COGNITO_USER_POOL_ID` | `auth.user_pool_id`         |
+//! | `NNM_COGNITO_CLIENT_ID`   | `auth.client_id`             |
+//! | `NNM_JWKS_TTL_SECS`       | `auth.jwks_ttl_secs`         |
+//! | `NNM_AUTH_STATIC_SECRET`  | `auth.static_secret`         |
+//! | `GATSBY_MYSHOPIFY_URL`    | `shopify.store_domain`       |
+//! | `SHOPIFY_STOREFRONT_KEY`  | `shopify.storefront_key`     |
+//! | `NNM_SHOPIFY_API_VERSION` | `shopify.api_version`        |
+//! | `NNM_SHOPIFY_TIMEOUT_SECS` | `shopify.timeout_secs`      |
+//! | `NNM_SHOPIFY_MAX_ATTEMPTS` | `shopify.max_attempts`      |
+//!
+//! # Example
+//!
+//! ```toml
+//! [server]
+//! bind_address = "0.0.0.0:3000"
+//!
+//! [storage]
+//! backend = "s3"
+//! presign_ttl_secs = 30
+//!
+//! [storage.buckets]
+//! us-east-1 = "nonothingissues1"
+//! us-east-2 = "nonothingissues"
+//!
+//! [shopify]
+//! store_domain = "example.myshopify.com"
+//! api_version = "2024-07"
+//! ```
+
+use std::{collections::BTreeMap, net::ToSocketAddrs, path::PathBuf, str::FromStr, time::Duration};
+
+/// The longest expiry S3 accepts for a presigned URL.
+const MAX_PRESIGN_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// More tries than this only keep a request waiting on a Shopify that is clearly unavailable.
+const MAX_SHOPIFY_ATTEMPTS: u32 = 10;
+
+/// S3 rejects multipart parts smaller than 5 MiB (except the last) or larger than 5 GiB.
+const MIN_PART_SIZE_MIB: u64 = 5;
+const MAX_PART_SIZE_MIB: u64 = 5 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Could not read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid value {value:?} for {var}: {reason}")]
+    Env {
+        var: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    S3,
+    Local,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s3" => Ok(StorageBackend::S3),
+            "local" => Ok(StorageBackend::Local),
+            _ => Err("expected `s3` or `local`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    DynamoDB,
+    Memory,
+    Sqlite,
+}
+
+impl FromStr for MetadataBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dynamodb" => Ok(MetadataBackend::DynamoDB),
+            "memory" => Ok(MetadataBackend::Memory),
+            "sqlite" => Ok(MetadataBackend::Sqlite),
+            _ => Err("expected `dynamodb`, `memory` or `sqlite`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Verify Cognito access tokens against the user pool's published keys.
+    Cognito,
+    /// Verify HS256 tokens signed with `auth.static_secret`, for tests and local runs.
+    Static,
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cognito" => Ok(AuthMode::Cognito),
+            "static" => Ok(AuthMode::Static),
+            _ => Err("expected `cognito` or `static`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "127.0.0.1:3000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalStorageConfig {
+    pub root: PathBuf,
+    /// Base URL that signed `/blob/{key}` links are built from.
+    pub public_url: String,
+    /// Key for signing blob URLs. A random key is generated at startup if unset.
+    pub signing_secret: Option<String>,
+}
+
+impl Default for LocalStorageConfig {
+    fn default() -> Self {
+        LocalStorageConfig {
+            root: PathBuf::from("./storage"),
+            public_url: "http://127.0.0.1:3000".to_string(),
+            signing_secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// The issue bucket for each AWS region the server may run in.
+    pub buckets: BTreeMap<String, String>,
+    pub presign_ttl_secs: u64,
+    /// Size of each part when streaming uploads to S3; smaller files use a single request.
+    pub multipart_part_size_mib: u64,
+    /// The largest issue PDF `/upload` accepts.
+    pub max_upload_mib: u64,
+    pub local: LocalStorageConfig,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::S3,
+            buckets: BTreeMap::from([
+                ("us-east-1".to_string(), "nonothingissues1".to_string()),
+                ("us-east-2".to_string(), "nonothingissues".to_string()),
+            ]),
+            presign_ttl_secs: 30,
+            multipart_part_size_mib: 8,
+            max_upload_mib: 256,
+            local: LocalStorageConfig::default(),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn presign_ttl(&self) -> Duration {
+        Duration::from_secs(self.presign_ttl_secs)
+    }
+
+    pub fn multipart_part_size(&self) -> usize {
+        (self.multipart_part_size_mib * 1024 * 1024) as usize
+    }
+
+    pub fn max_upload_size(&self) -> usize {
+        (self.max_upload_mib * 1024 * 1024) as usize
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    pub backend: MetadataBackend,
+    pub issue_table: String,
+    pub news_table: String,
+    /// The global secondary index of `news_table` that orders the news feed by date.
+    pub news_index: String,
+    pub sqlite_path: PathBuf,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        MetadataConfig {
+            backend: MetadataBackend::DynamoDB,
+            issue_table: "nnmIssueData".to_string(),
+            news_table: "nnmNews".to_string(),
+            news_index: "feed-published_at-index".to_string(),
+            sqlite_path: PathBuf::from("nnm.sqlite3"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// How long the issue index and metadata may be served before they are listed again.
+    pub issue_ttl_secs: u64,
+    /// How long Shopify product listings and products may be served before they are fetched again.
+    pub product_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            issue_ttl_secs: 60,
+            product_ttl_secs: 30,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn issue_ttl(&self) -> Duration {
+        Duration::from_secs(self.issue_ttl_secs)
+    }
+
+    pub fn product_ttl(&self) -> Duration {
+        Duration::from_secs(self.product_ttl_secs)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    pub region: String,
+    pub user_pool_id: String,
+    /// The app client tokens must have been issued to.
+    pub client_id: String,
+    /// How long the user pool's signing keys are cached.
+    pub jwks_ttl_secs: u64,
+    /// HS256 key for `static` mode.
+    pub static_secret: Option<String>,
+    /// Expected `iss` claim in `static` mode.
+    pub static_issuer: String,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            mode: AuthMode::Cognito,
+            region: "us-east-1".to_string(),
+            user_pool_id: String::new(),
+            client_id: String::new(),
+            jwks_ttl_secs: 60 * 60,
+            static_secret: None,
+            static_issuer: "nnm-local".to_string(),
+        }
+    }
+}
+
+impl AuthConfig {
+    /// The `iss` claim every accepted token must carry.
+    pub fn issuer(&self) -> String {
+        match self.mode {
+            AuthMode::Cognito => format!(
+                "https://cognito-idp.{}.amazonaws.com/{}",
+                self.region, self.user_pool_id
+            ),
+            AuthMode::Static => self.static_issuer.clone(),
+        }
+    }
+
+    pub fn jwks_url(&self) -> String {
+        format!("{}/.well-known/jwks.json", self.issuer())
+    }
+
+    pub fn jwks_ttl(&self) -> Duration {
+        Duration::from_secs(self.jwks_ttl_secs)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShopifyConfig {
+    pub store_domain: String,
+    pub storefront_key: String,
+    pub api_version: String,
+    /// How long a single request to Shopify may take, including reading the response.
+    pub timeout_secs: u64,
+    /// How many times a request is tried before a throttled or failing response is returned.
+    pub max_attempts: u32,
+}
+
+impl Default for ShopifyConfig {
+    fn default() -> Self {
+        ShopifyConfig {
+            store_domain: String::new(),
+            storefront_key: String::new(),
+            api_version: "2024-07".to_string(),
+            timeout_secs: 10,
+            max_attempts: 3,
+        }
+    }
+}
+
+impl ShopifyConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn graphql_url(&self) -> String {
+        format!(
+            "https://{}/api/{}/graphql",
+            self.store_domain, self.api_version
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub metadata: MetadataConfig,
+    pub cache: CacheConfig,
+    pub auth: AuthConfig,
+    pub shopify: ShopifyConfig,
+}
+
+/// Looks up an environment variable. `std::env::var` in production; tests pass a map.
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Reads `var` from `env`, converting it with `parse` and naming the variable if that fails.
+fn env_override<T>(
+    env: Env,
+    var: &'static str,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Option<T>, ConfigError> {
+    match env(var) {
+        Some(value) => {
+            parse(&value)
+                .map(Some)
+                .map_err(|reason| ConfigError::Env { var, value, reason })
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_string(s: &str) -> Result<String, String> {
+    Ok(s.to_string())
+}
+
+fn parse_buckets(s: &str) -> Result<BTreeMap<String, String>, String> {
+    s.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(region, bucket)| (region.trim().to_string(), bucket.trim().to_string()))
+                .ok_or(format!("expected `region=bucket`, got {:?}", pair))
+        })
+        .collect()
+}
+
+impl Config {
+    /// Loads the config file named by `NNM_CONFIG` (or `nnm.toml`), applies environment
+    /// overrides and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, required) = match std::env::var("NNM_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from("nnm.toml"), false),
+        };
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).map_err(|source| ConfigError::Parse { path, source })?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Config::default(),
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+        config.apply_env(&|var| std::env::var(var).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, env: Env) -> Result<(), ConfigError> {
+        if let Some(v) = env_override(env, "NNM_BIND_ADDRESS", parse_string)? {
+            self.server.bind_address = v;
+        }
+        if let Some(v) = env_override(env, "NNM_STORAGE_BACKEND", StorageBackend::from_str)? {
+            self.storage.backend = v;
+        }
+        if let Some(v) = env_override(env, "NNM_S3_BUCKETS", parse_buckets)? {
+            self.storage.buckets = v;
+        }
+        if let Some(v) = env_override(env, "NNM_PRESIGN_TTL_SECS", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.storage.presign_ttl_secs = v;
+        }
+        if let Some(v) = env_override(env, "NNM_MULTIPART_PART_SIZE_MIB", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.storage.multipart_part_size_mib = v;
+        }
+        if let Some(v) = env_override(env, "NNM_MAX_UPLOAD_MIB", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.storage.max_upload_mib = v;
+        }
+        if let Some(v) = env_override(env, "NNM_LOCAL_STORAGE_DIR", parse_string)? {
+            self.storage.local.root = PathBuf::from(v);
+        }
+        if let Some(v) = env_override(env, "NNM_PUBLIC_URL", parse_string)? {
+            self.storage.local.public_url = v;
+        }
+        if let Some(v) = env_override(env, "NNM_BLOB_SIGNING_SECRET", parse_string)? {
+            self.storage.local.signing_secret = Some(v);
+        }
+        if let Some(v) = env_override(env, "NNM_METADATA_BACKEND", MetadataBackend::from_str)? {
+            self.metadata.backend = v;
+        }
+        if let Some(v) = env_override(env, "NNM_ISSUE_TABLE", parse_string)? {
+            self.metadata.issue_table = v;
+        }
+        if let Some(v) = env_override(env, "NNM_NEWS_TABLE", parse_string)? {
+            self.metadata.news_table = v;
+        }
+        if let Some(v) = env_override(env, "NNM_NEWS_INDEX", parse_string)? {
+            self.metadata.news_index = v;
+        }
+        if let Some(v) = env_override(env, "NNM_SQLITE_PATH", parse_string)? {
+            self.metadata.sqlite_path = PathBuf::from(v);
+        }
+        if let Some(v) = env_override(env, "NNM_ISSUE_CACHE_TTL_SECS", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.cache.issue_ttl_secs = v;
+        }
+        if let Some(v) = env_override(env, "NNM_PRODUCT_CACHE_TTL_SECS", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.cache.product_ttl_secs = v;
+        }
+        if let Some(v) = env_override(env, "NNM_AUTH_MODE", AuthMode::from_str)? {
+            self.auth.mode = v;
+        }
+        if let Some(v) = env_override(env, "NNM_COGNITO_REGION", parse_string)? {
+            self.auth.region = v;
+        }
+        if let Some(v) = env_override(env, "NNM_COGNITO_USER_POOL_ID", parse_string)? {
+            self.auth.user_pool_id = v;
+        }
+        if let Some(v) = env_override(env, "NNM_COGNITO_CLIENT_ID", parse_string)? {
+            self.auth.client_id = v;
+        }
+        if let Some(v) = env_override(env, "NNM_JWKS_TTL_SECS", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.auth.jwks_ttl_secs = v;
+        }
+        if let Some(v) = env_override(env, "NNM_AUTH_STATIC_SECRET", parse_string)? {
+            self.auth.static_secret = Some(v);
+        }
+        if let Some(v) = env_override(env, "GATSBY_MYSHOPIFY_URL", parse_string)? {
+            self.shopify.store_domain = v;
+        }
+        if let Some(v) = env_override(env, "SHOPIFY_STOREFRONT_KEY", parse_string)? {
+            self.shopify.storefront_key = v;
+        }
+        if let Some(v) = env_override(env, "NNM_SHOPIFY_API_VERSION", parse_string)? {
+            self.shopify.api_version = v;
+        }
+        if let Some(v) = env_override(env, "NNM_SHOPIFY_TIMEOUT_SECS", |s| {
+            s.parse::<u64>().map_err(|e| e.to_string())
+        })? {
+            self.shopify.timeout_secs = v;
+        }
+        if let Some(v) = env_override(env, "NNM_SHOPIFY_MAX_ATTEMPTS", |s| {
+            s.parse::<u32>().map_err(|e| e.to_string())
+        })? {
+            self.shopify.max_attempts = v;
+        }
+        Ok(())
+    }
+
+    /// Collects every problem with the configuration, rather than stopping at the first.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self
+            .server
+            .bind_address
+            .to_socket_addrs()
+            .map(|mut addrs| addrs.next().is_none())
+            .unwrap_or(true)
+        {
+            problems.push(format!(
+                "server.bind_address {:?} is not a valid socket address",
+                self.server.bind_address
+            ));
+        }
+
+        if self.storage.presign_ttl_secs == 0
+            || self.storage.presign_ttl_secs > MAX_PRESIGN_TTL_SECS
+        {
+            problems.push(format!(
+                "storage.presign_ttl_secs must be between 1 and {}",
+                MAX_PRESIGN_TTL_SECS
+            ));
+        }
+        if !(MIN_PART_SIZE_MIB..=MAX_PART_SIZE_MIB).contains(&self.storage.multipart_part_size_mib)
+        {
+            problems.push(format!(
+                "storage.multipart_part_size_mib must be between {} and {}",
+                MIN_PART_SIZE_MIB, MAX_PART_SIZE_MIB
+            ));
+        }
+        if self.storage.max_upload_mib == 0 {
+            problems.push("storage.max_upload_mib must be at least 1".to_string());
+        }
+        match self.storage.backend {
+            StorageBackend::S3 => {
+                if self.storage.buckets.is_empty() {
+                    problems.push("storage.buckets must name at least one bucket".to_string());
+                }
+                for (region, bucket) in &self.storage.buckets {
+                    if bucket.is_empty() {
+                        problems.push(format!("storage.buckets.{} is empty", region));
+                    }
+                }
+            }
+            StorageBackend::Local => {
+                if self.storage.local.public_url.is_empty() {
+                    problems.push("storage.local.public_url is required".to_string());
+                }
+            }
+        }
+
+        if self.metadata.backend == MetadataBackend::DynamoDB {
+            if self.metadata.issue_table.is_empty() {
+                problems.push("metadata.issue_table is required".to_string());
+            }
+            if self.metadata.news_table.is_empty() {
+                problems.push("metadata.news_table is required".to_string());
+            }
+            if self.metadata.news_index.is_empty() {
+                problems.push("metadata.news_index is required".to_string());
+            }
+        }
+
+        if self.cache.issue_ttl_secs == 0 {
+            problems.push("cache.issue_ttl_secs must be at least 1".to_string());
+        }
+        if self.cache.product_ttl_secs == 0 {
+            problems.push("cache.product_ttl_secs must be at least 1".to_string());
+        }
+
+        if self.auth.client_id.is_empty() {
+            problems.push("auth.client_id is required (or set NNM_COGNITO_CLIENT_ID)".to_string());
+        }
+        match self.auth.mode {
+            AuthMode::Cognito => {
+                if self.auth.user_pool_id.is_empty() {
+                    problems.push(
+                        "auth.user_pool_id is required (or set NNM_COGNITO_USER_POOL_ID)"
+                            .to_string(),
+                    );
+                }
+                if self.auth.region.is_empty() {
+                    problems.push("auth.region is required".to_string());
+                }
+                if self.auth.jwks_ttl_secs == 0 {
+                    problems.push("auth.jwks_ttl_secs must be at least 1".to_string());
+                }
+            }
+            AuthMode::Static => {
+                if self
+                    .auth
+                    .static_secret
+                    .as_deref()
+                    .unwrap_or_default()
+                    .is_empty()
+                {
+                    problems.push(
+                        "auth.static_secret is required in static mode (or set NNM_AUTH_STATIC_SECRET)"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+
+        if self.shopify.store_domain.is_empty() {
+            problems
+                .push("shopify.store_domain is required (or set GATSBY_MYSHOPIFY_URL)".to_string());
+        }
+        if self.shopify.storefront_key.is_empty() {
+            problems.push(
+                "shopify.storefront_key is required (or set SHOPIFY_STOREFRONT_KEY)".to_string(),
+            );
+        }
+        if self.shopify.api_version.is_empty() {
+            problems.push("shopify.api_version is required".to_string());
+        }
+        if self.shopify.timeout_secs == 0 {
+            problems.push("shopify.timeout_secs must be greater than 0".to_string());
+        }
+        if self.shopify.max_attempts == 0 || self.shopify.max_attempts > MAX_SHOPIFY_ATTEMPTS {
+            problems.push(format!(
+                "shopify.max_attempts must be between 1 and {}",
+                MAX_SHOPIFY_ATTEMPTS
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Makes one setting of a valid config invalid.
+    type Breakage = fn(&mut Config);
+
+    /// A config that passes validation, for tests to break one setting of.
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.auth.client_id = "client".to_string();
+        config.auth.user_pool_id = "us-east-1_pool".to_string();
+        config.shopify.store_domain = "example.myshopify.com".to_string();
+        config.shopify.storefront_key = "key".to_string();
+        config
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    fn apply(config: &mut Config, vars: &[(&str, &str)]) -> Result<(), ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect();
+        config.apply_env(&|var| vars.get(var).cloned())
+    }
+
+    fn toml(value: &impl serde::Serialize) -> String {
+        toml::to_string(value).unwrap()
+    }
+
+    #[test]
+    fn empty_file_uses_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(toml(&config), toml(&Config::default()));
+        assert_eq!(config.server.bind_address, "127.0.0.1:3000");
+        assert_eq!(config.storage.backend, StorageBackend::S3);
+        assert_eq!(config.storage.buckets.len(), 2);
+        assert_eq!(config.storage.presign_ttl_secs, 30);
+        assert_eq!(config.storage.multipart_part_size_mib, 8);
+        assert_eq!(config.storage.max_upload_mib, 256);
+        assert_eq!(config.metadata.backend, MetadataBackend::DynamoDB);
+        assert_eq!(config.metadata.news_index, "feed-published_at-index");
+        assert_eq!(config.cache.issue_ttl_secs, 60);
+        assert_eq!(config.cache.product_ttl_secs, 30);
+        assert_eq!(config.auth.mode, AuthMode::Cognito);
+        assert_eq!(config.auth.jwks_ttl_secs, 3600);
+        assert_eq!(config.shopify.api_version, "2024-07");
+        assert_eq!(config.shopify.timeout_secs, 10);
+        assert_eq!(config.shopify.max_attempts, 3);
+    }
+
+    #[test]
+    fn example_file_matches_defaults() {
+        let config: Config = toml::from_str(include_str!("../nnm.example.toml")).unwrap();
+        assert_eq!(toml(&config), toml(&Config::default()));
+    }
+
+    #[test]
+    fn partial_sections_keep_other_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [storage]
+            backend = "local"
+
+            [storage.local]
+            root = "/srv/nnm"
+
+            [shopify]
+            max_attempts = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.storage.backend, StorageBackend::Local);
+        assert_eq!(config.storage.local.root, PathBuf::from("/srv/nnm"));
+        assert_eq!(config.storage.local.public_url, "http://127.0.0.1:3000");
+        assert_eq!(config.storage.presign_ttl_secs, 30);
+        assert_eq!(config.shopify.max_attempts, 5);
+        assert_eq!(config.shopify.timeout_secs, 10);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<Config>("[storage]\npresign_ttl = 30\n").is_err());
+        assert!(toml::from_str::<Config>("[nonsense]\n").is_err());
+        assert!(toml::from_str::<Config>("[metadata]\nbackend = \"postgres\"\n").is_err());
+    }
+
+    #[test]
+    fn no_environment_changes_nothing() {
+        let mut config = valid();
+        apply(&mut config, &[]).unwrap();
+        assert_eq!(toml(&config), toml(&valid()));
+    }
+
+    #[test]
+    fn every_variable_overrides_its_setting() {
+        let mut config = Config::default();
+        apply(
+            &mut config,
+            &[
+                ("NNM_BIND_ADDRESS", "0.0.0.0:8080"),
+                ("NNM_STORAGE_BACKEND", "local"),
+                ("NNM_S3_BUCKETS", "eu-west-1=euro, us-west-2 = west,"),
+                ("NNM_PRESIGN_TTL_SECS", "120"),
+                ("NNM_MULTIPART_PART_SIZE_MIB", "16"),
+                ("NNM_MAX_UPLOAD_MIB", "64"),
+                ("NNM_LOCAL_STORAGE_DIR", "/srv/blobs"),
+                ("NNM_PUBLIC_URL", "https://nnm.example"),
+                ("NNM_BLOB_SIGNING_SECRET", "blob-secret"),
+                ("NNM_METADATA_BACKEND", "sqlite"),
+                ("NNM_ISSUE_TABLE", "issues"),
+                ("NNM_NEWS_TABLE", "news"),
+                ("NNM_NEWS_INDEX", "news-index"),
+                ("NNM_SQLITE_PATH", "/srv/nnm.sqlite3"),
+                ("NNM_ISSUE_CACHE_TTL_SECS", "5"),
+                ("NNM_PRODUCT_CACHE_TTL_SECS", "6"),
+                ("NNM_AUTH_MODE", "static"),
+                ("NNM_COGNITO_REGION", "eu-west-1"),
+                ("NNM_COGNITO_USER_POOL_ID", "eu-west-1_pool"),
+                ("NNM_COGNITO_CLIENT_ID", "client"),
+                ("NNM_JWKS_TTL_SECS", "7"),
+                ("NNM_AUTH_STATIC_SECRET", "auth-secret"),
+                ("GATSBY_MYSHOPIFY_URL", "shop.myshopify.com"),
+                ("SHOPIFY_STOREFRONT_KEY", "storefront"),
+                ("NNM_SHOPIFY_API_VERSION", "2025-01"),
+                ("NNM_SHOPIFY_TIMEOUT_SECS", "8"),
+                ("NNM_SHOPIFY_MAX_ATTEMPTS", "9"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind_address, "0.0.0.0:8080");
+        assert_eq!(config.storage.backend, StorageBackend::Local);
+        assert_eq!(
+            config.storage.buckets,
+            BTreeMap::from([
+                ("eu-west-1".to_string(), "euro".to_string()),
+                ("us-west-2".to_string(), "west".to_string()),
+            ])
+        );
+        assert_eq!(config.storage.presign_ttl_secs, 120);
+        assert_eq!(config.storage.multipart_part_size_mib, 16);
+        assert_eq!(config.storage.max_upload_mib, 64);
+        assert_eq!(config.storage.local.root, PathBuf::from("/srv/blobs"));
+        assert_eq!(config.storage.local.public_url, "https://nnm.example");
+        assert_eq!(
+            config.storage.local.signing_secret.as_deref(),
+            Some("blob-secret")
+        );
+        assert_eq!(config.metadata.backend, MetadataBackend::Sqlite);
+        assert_eq!(config.metadata.issue_table, "issues");
+        assert_eq!(config.metadata.news_table, "news");
+        assert_eq!(config.metadata.news_index, "news-index");
+        assert_eq!(
+            config.metadata.sqlite_path,
+            PathBuf::from("/srv/nnm.sqlite3")
+        );
+        assert_eq!(config.cache.issue_ttl_secs, 5);
+        assert_eq!(config.cache.product_ttl_secs, 6);
+        assert_eq!(config.auth.mode, AuthMode::Static);
+        assert_eq!(config.auth.region, "eu-west-1");
+        assert_eq!(config.auth.user_pool_id, "eu-west-1_pool");
+        assert_eq!(config.auth.client_id, "client");
+        assert_eq!(config.auth.jwks_ttl_secs, 7);
+        assert_eq!(config.auth.static_secret.as_deref(), Some("auth-secret"));
+        assert_eq!(config.shopify.store_domain, "shop.myshopify.com");
+        assert_eq!(config.shopify.storefront_key, "storefront");
+        assert_eq!(config.shopify.api_version, "2025-01");
+        assert_eq!(config.shopify.timeout_secs, 8);
+        assert_eq!(config.shopify.max_attempts, 9);
+    }
+
+    #[test]
+    fn invalid_variables_are_named() {
+        for (var, value) in [
+            ("NNM_STORAGE_BACKEND", "gcs"),
+            ("NNM_S3_BUCKETS", "us-east-1"),
+            ("NNM_PRESIGN_TTL_SECS", "soon"),
+            ("NNM_MULTIPART_PART_SIZE_MIB", "-1"),
+            ("NNM_MAX_UPLOAD_MIB", "1.5"),
+            ("NNM_METADATA_BACKEND", "postgres"),
+            ("NNM_ISSUE_CACHE_TTL_SECS", ""),
+            ("NNM_PRODUCT_CACHE_TTL_SECS", "x"),
+            ("NNM_AUTH_MODE", "oauth"),
+            ("NNM_JWKS_TTL_SECS", "1h"),
+            ("NNM_SHOPIFY_TIMEOUT_SECS", "ten"),
+            ("NNM_SHOPIFY_MAX_ATTEMPTS", "99999999999"),
+        ] {
+            match apply(&mut Config::default(), &[(var, value)]) {
+                Err(ConfigError::Env {
+                    var: named,
+                    value: given,
+                    ..
+                }) => {
+                    assert_eq!((named, given.as_str()), (var, value));
+                }
+                other => panic!("{}={:?} gave {:?}", var, value, other),
+            }
+        }
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        assert!(problems(&valid()).is_empty());
+
+        let mut local = valid();
+        local.storage.backend = StorageBackend::Local;
+        local.storage.buckets.clear();
+        local.metadata.backend = MetadataBackend::Memory;
+        local.metadata.issue_table.clear();
+        local.auth.mode = AuthMode::Static;
+        local.auth.user_pool_id.clear();
+        local.auth.static_secret = Some("secret".to_string());
+        assert!(problems(&local).is_empty());
+    }
+
+    #[test]
+    fn each_invalid_setting_is_reported() {
+        let cases: Vec<(Breakage, &str)> = vec![
+            (
+                |c| c.server.bind_address = "not an address".to_string(),
+                "server.bind_address \"not an address\" is not a valid socket address",
+            ),
+            (
+                |c| c.storage.presign_ttl_secs = 0,
+                "storage.presign_ttl_secs must be between 1 and 604800",
+            ),
+            (
+                |c| c.storage.presign_ttl_secs = MAX_PRESIGN_TTL_SECS + 1,
+                "storage.presign_ttl_secs must be between 1 and 604800",
+            ),
+            (
+                |c| c.storage.multipart_part_size_mib = 4,
+                "storage.multipart_part_size_mib must be between 5 and 5120",
+            ),
+            (
+                |c| c.storage.multipart_part_size_mib = 5121,
+                "storage.multipart_part_size_mib must be between 5 and 5120",
+            ),
+            (
+                |c| c.storage.max_upload_mib = 0,
+                "storage.max_upload_mib must be at least 1",
+            ),
+            (
+                |c| c.storage.buckets.clear(),
+                "storage.buckets must name at least one bucket",
+            ),
+            (
+                |c| {
+                    c.storage
+                        .buckets
+                        .insert("eu-west-1".to_string(), String::new());
+                },
+                "storage.buckets.eu-west-1 is empty",
+            ),
+            (
+                |c| {
+                    c.storage.backend = StorageBackend::Local;
+                    c.storage.local.public_url.clear();
+                },
+                "storage.local.public_url is required",
+            ),
+            (
+                |c| c.metadata.issue_table.clear(),
+                "metadata.issue_table is required",
+            ),
+            (
+                |c| c.metadata.news_table.clear(),
+                "metadata.news_table is required",
+            ),
+            (
+                |c| c.metadata.news_index.clear(),
+                "metadata.news_index is required",
+            ),
+            (
+                |c| c.cache.issue_ttl_secs = 0,
+                "cache.issue_ttl_secs must be at least 1",
+            ),
+            (
+                |c| c.cache.product_ttl_secs = 0,
+                "cache.product_ttl_secs must be at least 1",
+            ),
+            (
+                |c| c.auth.client_id.clear(),
+                "auth.client_id is required (or set NNM_COGNITO_CLIENT_ID)",
+            ),
+            (
+                |c| c.auth.user_pool_id.clear(),
+                "auth.user_pool_id is required (or set NNM_COGNITO_USER_POOL_ID)",
+            ),
+            (|c| c.auth.region.clear(), "auth.region is required"),
+            (
+                |c| c.auth.jwks_ttl_secs = 0,
+                "auth.jwks_ttl_secs must be at least 1",
+            ),
+            (
+                |c| c.auth.mode = AuthMode::Static,
+                "auth.static_secret is required in static mode (or set NNM_AUTH_STATIC_SECRET)",
+            ),
+            (
+                |c| {
+                    c.auth.mode = AuthMode::Static;
+                    c.auth.static_secret = Some(String::new());
+                },
+                "auth.static_secret is required in static mode (or set NNM_AUTH_STATIC_SECRET)",
+            ),
+            (
+                |c| c.shopify.store_domain.clear(),
+                "shopify.store_domain is required (or set GATSBY_MYSHOPIFY_URL)",
+            ),
+            (
+                |c| c.shopify.storefront_key.clear(),
+                "shopify.storefront_key is required (or set SHOPIFY_STOREFRONT_KEY)",
+            ),
+            (
+                |c| c.shopify.api_version.clear(),
+                "shopify.api_version is required",
+            ),
+            (
+                |c| c.shopify.timeout_secs = 0,
+                "shopify.timeout_secs must be greater than 0",
+            ),
+            (
+                |c| c.shopify.max_attempts = 0,
+                "shopify.max_attempts must be between 1 and 10",
+            ),
+            (
+                |c| c.shopify.max_attempts = MAX_SHOPIFY_ATTEMPTS + 1,
+                "shopify.max_attempts must be between 1 and 10",
+            ),
+        ];
+        for (break_setting, expected) in cases {
+            let mut config = valid();
+            break_setting(&mut config);
+            assert_eq!(problems(&config), [expected]);
+        }
+    }
+
+    #[test]
+    fn backend_specific_settings_are_only_checked_for_that_backend() {
+        let mut config = valid();
+        config.metadata.backend = MetadataBackend::Sqlite;
+        config.metadata.issue_table.clear();
+        config.metadata.news_index.clear();
+        config.storage.backend = StorageBackend::Local;
+        config.storage.buckets.clear();
+        config.auth.mode = AuthMode::Static;
+        config.auth.static_secret = Some("secret".to_string());
+        config.auth.user_pool_id.clear();
+        config.auth.jwks_ttl_secs = 0;
+        assert!(problems(&config).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = valid();
+        config.cache.issue_ttl_secs = 0;
+        config.shopify.storefront_key.clear();
+        config.storage.max_upload_mib = 0;
+        assert_eq!(
+            problems(&config),
+            [
+                "storage.max_upload_mib must be at least 1",
+                "cache.issue_ttl_secs must be at least 1",
+                "shopify.storefront_key is required (or set SHOPIFY_STOREFRONT_KEY)",
+            ]
+        );
+    }
+}
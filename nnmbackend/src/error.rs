@@ -0,0 +1,380 @@
+//! This module defines the error type returned by every route.
+//!
+//! Handlers return `Result<_, ApiError>`, and actix renders the error through its
+//! `ResponseError` implementation. Every failure therefore reaches the client with a
+//! meaningful status code and the same JSON body:
+//!
+//! ```json
+//! { "code": "not_found", "message": "Object not found: nnm_issues/issue_9.pdf", "details": null }
+//! ```
+//!
+//! # Status codes
+//!
+//! - `BadRequest` (400): The request itself was malformed.
+//! - `Unauthorized` (401): The bearer token is missing or invalid.
+//! - `Forbidden` (403): The request was authenticated but is not allowed, e.g. a role is missing.
+//! - `NotFound` (404): The requested issue, object or record does not exist.
+//! - `Conflict` (409): The request would replace something that already exists.
+//! - `Unprocessable` (422): Shopify rejected the request with `userErrors`.
+//! - `Upstream` (502): S3, DynamoDB, Cognito's key set or Shopify failed or answered with something unusable.
+//! - `Replication` (502): A write reached some regional buckets but not all; `details` lists each region.
+//! - `Internal` (500): Anything else.
+//!
+//! The extractor configs returned by `json_config`, `path_config`, `query_config` and
+//! `multipart_config` route actix's own extraction failures through `ApiError` as well.
+
+use actix_multipart::form::MultipartFormConfig;
+use actix_web::{
+    http::StatusCode,
+    web::{JsonConfig, PathConfig, QueryConfig},
+    HttpResponse, ResponseError,
+};
+use serde_json::Value;
+
+use crate::utils::{metadata::MetadataError, shopify::ShopifyError, storage::StorageError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{message}")]
+    BadRequest {
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{message}")]
+    Unprocessable {
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("{0}")]
+    Upstream(String),
+    #[error("{message}")]
+    Replication {
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("{0}")]
+    Internal(String),
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ApiErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::BadRequest {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// A machine-readable name for the error, stable across message wording changes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest { .. } => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unprocessable { .. } => "unprocessable_entity",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Replication { .. } => "replication_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::BadRequest { details, .. }
+            | ApiError::Unprocessable { details, .. }
+            | ApiError::Replication { details, .. } => details.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Upstream(_) | ApiError::Replication { .. } => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ApiErrorBody {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            details: self.details(),
+        })
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            StorageError::InvalidKey(_) => ApiError::bad_request(e.to_string()),
+            StorageError::Backend(_) => ApiError::Upstream(e.to_string()),
+        }
+    }
+}
+
+impl From<MetadataError> for ApiError {
+    fn from(e: MetadataError) -> Self {
+        match e {
+            MetadataError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            MetadataError::Malformed(_) => ApiError::Internal(e.to_string()),
+            MetadataError::Backend(_) => ApiError::Upstream(e.to_string()),
+        }
+    }
+}
+
+impl From<ShopifyError> for ApiError {
+    fn from(e: ShopifyError) -> Self {
+        match e {
+            ShopifyError::UserErrors(errors) => ApiError::Unprocessable {
+                message: "Shopify rejected the request".to_string(),
+                details: serde_json::to_value(errors).ok(),
+            },
+            // A request that cannot be encoded is a bug here, not a problem with Shopify
+            ShopifyError::Encode(_) => ApiError::Internal(e.to_string()),
+            _ => ApiError::Upstream(e.to_string()),
+        }
+    }
+}
+
+pub fn json_config() -> JsonConfig {
+    JsonConfig::default().error_handler(|e, _| ApiError::bad_request(e.to_string()).into())
+}
+
+pub fn path_config() -> PathConfig {
+    PathConfig::default().error_handler(|e, _| ApiError::bad_request(e.to_string()).into())
+}
+
+pub fn query_config() -> QueryConfig {
+    QueryConfig::default().error_handler(|e, _| ApiError::bad_request(e.to_string()).into())
+}
+
+/// `total_limit` caps the size of a whole multipart body, including every file in it.
+pub fn multipart_config(total_limit: usize) -> MultipartFormConfig {
+    MultipartFormConfig::default()
+        .total_limit(total_limit)
+        .error_handler(|e, _| ApiError::bad_request(e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    use super::*;
+    use crate::utils::shopify::graphql::api::UserError;
+
+    /// The status and JSON body `error` is rendered as.
+    async fn render(error: &ApiError) -> (StatusCode, Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn every_variant_renders_its_status_and_body() {
+        let details = json!({ "field": "issue_number" });
+        let cases = [
+            (
+                ApiError::BadRequest {
+                    message: "Bad".to_string(),
+                    details: Some(details.clone()),
+                },
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+                "Bad",
+                Some(details.clone()),
+            ),
+            (
+                ApiError::Unauthorized("No token".to_string()),
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "No token",
+                None,
+            ),
+            (
+                ApiError::Forbidden("No role".to_string()),
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "No role",
+                None,
+            ),
+            (
+                ApiError::NotFound("Missing".to_string()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Missing",
+                None,
+            ),
+            (
+                ApiError::Conflict("Exists".to_string()),
+                StatusCode::CONFLICT,
+                "conflict",
+                "Exists",
+                None,
+            ),
+            (
+                ApiError::Unprocessable {
+                    message: "Rejected".to_string(),
+                    details: Some(details.clone()),
+                },
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unprocessable_entity",
+                "Rejected",
+                Some(details.clone()),
+            ),
+            (
+                ApiError::Upstream("S3 is down".to_string()),
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+                "S3 is down",
+                None,
+            ),
+            (
+                ApiError::Replication {
+                    message: "Partly stored".to_string(),
+                    details: Some(details.clone()),
+                },
+                StatusCode::BAD_GATEWAY,
+                "replication_failed",
+                "Partly stored",
+                Some(details.clone()),
+            ),
+            (
+                ApiError::Internal("Oops".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Oops",
+                None,
+            ),
+        ];
+
+        for (error, status, code, message, details) in cases {
+            assert_eq!(error.status_code(), status, "{:?}", error);
+            assert_eq!(
+                render(&error).await,
+                (
+                    status,
+                    json!({ "code": code, "message": message, "details": details })
+                ),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn storage_errors_convert_by_kind() {
+        let cases = [
+            (
+                StorageError::NotFound("nnm_issues/issue_9.pdf".to_string()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Object not found: nnm_issues/issue_9.pdf",
+            ),
+            (
+                StorageError::InvalidKey("../etc".to_string()),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+                "Invalid key: ../etc",
+            ),
+            (
+                StorageError::Backend(anyhow!("timed out")),
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+                "Storage backend error: timed out",
+            ),
+        ];
+
+        for (error, status, code, message) in cases {
+            let error = ApiError::from(error);
+            assert_eq!(
+                render(&error).await,
+                (
+                    status,
+                    json!({ "code": code, "message": message, "details": null })
+                )
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn metadata_errors_convert_by_kind() {
+        let cases = [
+            (
+                MetadataError::NotFound("issue 9".to_string()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "Record not found: issue 9",
+            ),
+            (
+                MetadataError::Malformed("number".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Malformed record: number",
+            ),
+            (
+                MetadataError::Backend(anyhow!("throttled")),
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+                "Metadata backend error: throttled",
+            ),
+        ];
+
+        for (error, status, code, message) in cases {
+            let error = ApiError::from(error);
+            assert_eq!(
+                render(&error).await,
+                (
+                    status,
+                    json!({ "code": code, "message": message, "details": null })
+                )
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn shopify_user_errors_are_unprocessable_with_details() {
+        let error = ApiError::from(ShopifyError::UserErrors(vec![UserError {
+            field: Some(vec!["lines".to_string(), "0".to_string()]),
+            message: "Quantity is too large".to_string(),
+            code: Some("INVALID".to_string()),
+        }]));
+        let (status, body) = render(&error).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "unprocessable_entity");
+        assert_eq!(body["details"][0]["message"], "Quantity is too large");
+
+        let error = ApiError::from(ShopifyError::Throttled);
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+        let error = ApiError::from(ShopifyError::Encode("bad variable".to_string()));
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -1,41 +1,93 @@
 use actix_cors::Cors;
-use actix_web::{http, App, HttpServer};
+use actix_web::{http, web, web::Data, App, HttpServer};
 
+mod auth;
+mod config;
+mod error;
 mod routes;
+mod state;
+#[cfg(test)]
+mod testing;
 mod utils;
 
+use config::Config;
 use routes::{
-    dynamodb::get_issue_data, 
-    news::get_news, 
-    s3::{count_issues, get_issue, get_latest_issue}, 
-    shopify::{create_checkout, execute_checkout, get_checkout}, 
-    upload::upload
+    blob::serve_blob,
+    dynamodb::get_issue_data,
+    issues::{delete_issue, list_issues, replace_issue_file, update_issue},
+    news::{create_news, delete_news, get_news, preview_news, update_news, NEWS_MULTIPART_LIMIT},
+    products::{get_product, get_products},
+    s3::{count_issues, get_issue, get_latest_issue},
+    shopify::{
+        add_checkout_lines, create_checkout, execute_checkout, get_checkout, remove_checkout_lines,
+        update_checkout_lines,
+    },
+    upload::upload,
 };
+use state::AppState;
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
-    println!("Starting server...");
-    HttpServer::new(|| {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let bind_address = config.server.bind_address.clone();
+    log::info!("Starting server on {}...", bind_address);
+    let state = Data::new(AppState::new(config).await?);
+    state.issue_cache.clone().spawn_refresher();
+    // Leave room for the text fields alongside the largest allowed PDF
+    let multipart_limit = state.config.storage.max_upload_size() + 1024 * 1024;
+    HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
             .max_age(3600)
             .send_wildcard();
         App::new()
             .wrap(cors)
+            .app_data(state.clone())
+            .app_data(error::json_config())
+            .app_data(error::path_config())
+            .app_data(error::query_config())
+            .app_data(error::multipart_config(multipart_limit))
+            .service(serve_blob)
             .service(count_issues)
             .service(get_issue)
             .service(get_latest_issue)
             .service(get_issue_data)
+            .service(list_issues)
+            .service(update_issue)
+            .service(replace_issue_file)
+            .service(delete_issue)
             .service(create_checkout)
             .service(get_checkout)
             .service(execute_checkout)
+            .service(add_checkout_lines)
+            .service(update_checkout_lines)
+            .service(remove_checkout_lines)
+            .service(get_products)
+            .service(get_product)
             .service(upload)
-            .service(get_news)
+            .service(
+                // News images are far smaller than issue PDFs, so the news routes get a
+                // tighter multipart limit than the app-wide one
+                web::scope("/news")
+                    .app_data(error::multipart_config(NEWS_MULTIPART_LIMIT))
+                    .service(get_news)
+                    .service(preview_news)
+                    .service(create_news)
+                    .service(update_news)
+                    .service(delete_news),
+            )
     })
-    .bind("127.0.0.1:3000")?
+    .bind(bind_address)?
     .run()
     .await
 }
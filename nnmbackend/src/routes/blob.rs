@@ -0,0 +1,80 @@
+//! This module defines the route that serves objects from the local blob store.
+//!
+//! When the server runs with the local filesystem backend, signed URLs point at this route
+//! instead of at S3. In any other mode the route always responds with `NotFound`.
+//!
+//! # Routes
+//!
+//! - `GET /blob/{key}?expires={unix}&signature={hex}`: Returns the object if the signature is valid and unexpired.
+//!
+//! # Errors
+//!
+//! This route returns an `ApiError`: `Forbidden` for an invalid or expired signature and
+//! `NotFound` if the object does not exist.
+use actix_web::web::{Data, Path, Query};
+
+use crate::{error::ApiError, state::AppState, utils::storage::BlobStore};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SignedBlobQuery {
+    pub expires: u64,
+    pub signature: String,
+}
+
+fn content_type_for(key: &str) -> &'static str {
+    match key
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+    {
+        Some(ext) if ext == "pdf" => "application/pdf",
+        Some(ext) if ext == "png" => "image/png",
+        Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
+        Some(ext) if ext == "gif" => "image/gif",
+        Some(ext) if ext == "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[actix_web::get("/blob/{key:.*}")]
+async fn serve_blob(
+    key: Path<String>,
+    query: Query<SignedBlobQuery>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let Some(store) = &state.local_blob_store else {
+        return Err(ApiError::NotFound(
+            "Local blob storage is not enabled".to_string(),
+        ));
+    };
+    let key = key.into_inner();
+    if !store.verify(&key, query.expires, &query.signature) {
+        return Err(ApiError::Forbidden(
+            "Invalid or expired signature".to_string(),
+        ));
+    }
+    let bytes = store.get(&key).await?;
+    Ok(actix_web::HttpResponse::Ok()
+        .content_type(content_type_for(&key))
+        .body(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_types_cover_every_stored_extension() {
+        for (key, content_type) in [
+            ("nnm_issues/issue_1.pdf", "application/pdf"),
+            ("nnm_covers/issue_1.PNG", "image/png"),
+            ("nnm_news/a.jpg", "image/jpeg"),
+            ("nnm_news/a.jpeg", "image/jpeg"),
+            ("nnm_news/a.gif", "image/gif"),
+            ("nnm_news/a.webp", "image/webp"),
+            ("nnm_news/a.bin", "application/octet-stream"),
+            ("nnm_news/no_extension", "application/octet-stream"),
+        ] {
+            assert_eq!(content_type_for(key), content_type, "{}", key);
+        }
+    }
+}
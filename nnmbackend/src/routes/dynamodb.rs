@@ -1,46 +1,53 @@
-/// This module defines the route for fetching issue data from DynamoDB.
-///
-/// The route is defined using Actix-web and interacts with DynamoDB through utility functions.
-///
-/// # Routes
-///
-/// - `GET /issuedata/{issue_number}`: Fetches issue data for the given issue number.
-///
-/// # Functions
-///
-/// - `get_issue_data`: Asynchronously fetches issue data from DynamoDB and returns an HTTP response.
-///
-/// # Example
-///
-/// ```
-/// use actix_web::{web, App, HttpServer};
-/// use nnmbackend::routes::dynamodb::get_issue_data;
-///
-/// #[actix_web::main]
-/// async fn main() -> std::io::Result<()> {
-///     HttpServer::new(|| {
-///         App::new()
-///             .route("/issuedata/{issue_number}", web::get().to(get_issue_data))
-///     })
-///     .bind("127.0.0.1:8080")?
-///     .run()
-///     .await
-/// }
-/// ```
-///
-/// # Errors
-///
-/// This function returns an `InternalServerError` if there is an issue fetching data from DynamoDB.
+//! This module defines the route for fetching issue data from DynamoDB.
+//!
+//! The route is defined using Actix-web and reads from whichever `MetadataRepository` the server was started with.
+//! Records are served from the issue cache when present, falling back to the repository otherwise.
+//!
+//! # Routes
+//!
+//! - `GET /issuedata/{issue_number}`: Fetches issue data for the given issue number.
+//!
+//! # Functions
+//!
+//! - `get_issue_data`: Asynchronously fetches issue data from the metadata repository and returns an HTTP response.
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{web, App, HttpServer};
+//! use nnmbackend::routes::dynamodb::get_issue_data;
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| {
+//!         App::new()
+//!             .route("/issuedata/{issue_number}", web::get().to(get_issue_data))
+//!     })
+//!     .bind("127.0.0.1:8080")?
+//!     .run()
+//!     .await
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! This function returns an `ApiError`: `NotFound` if there is no record for the issue and `Upstream`
+//! if the metadata repository cannot be reached.
 
-use crate::utils::dynamodb as db;
-use actix_web::web::Path;
+use crate::{error::ApiError, state::AppState};
+use actix_web::web::{Data, Path};
 
 #[actix_web::get("/issuedata/{issue_number}")]
-async fn get_issue_data(issue_number: Path<usize>) -> actix_web::HttpResponse {
-    // Get issue data from database
-    let client = db::get_db_client().await.unwrap();
-    match db::get_issue_data(issue_number.into_inner(), &client).await {
-        Ok(issue) => actix_web::HttpResponse::Ok().body(serde_json::to_string(&issue).unwrap()),
-        Err(e) => actix_web::HttpResponse::InternalServerError().body(format!("Error: {}", e)),
+async fn get_issue_data(
+    issue_number: Path<usize>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let issue_number = issue_number.into_inner();
+    let snapshot = state.issue_cache.snapshot().await?;
+    if let Some(issue) = snapshot.record(issue_number) {
+        return Ok(actix_web::HttpResponse::Ok().json(issue));
     }
+    // Get issue data from database
+    let issue = state.metadata.get_issue(issue_number).await?;
+    Ok(actix_web::HttpResponse::Ok().json(issue))
 }
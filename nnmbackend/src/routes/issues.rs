@@ -0,0 +1,280 @@
+//! This module defines the routes for browsing and administering the issue catalog.
+//!
+//! The catalog route joins the issue PDFs in the blob store with their metadata records, so the
+//! frontend's catalog page can render every issue from a single request. Both come from the issue
+//! cache. The admin routes require an `Authorization: Bearer <token>` header carrying a Cognito
+//! access token: editing needs the `editor` role and deleting needs the `admin` role.
+//!
+//! # Routes
+//!
+//! - `GET /issues?limit={n}&cursor={cursor}&contributor={handle}`: Returns one page of issues, newest first.
+//!   An issue whose cover cannot be signed is still returned, with `cover_url` set to `null`.
+//! - `PATCH /issues/{issue_number}`: Updates the blurb and/or contributors from a JSON body.
+//! - `PUT /issues/{issue_number}/file`: Replaces the PDF of a published issue from a multipart `file` field.
+//! - `DELETE /issues/{issue_number}`: Removes the PDF from every region and then the metadata record.
+//!
+//! # Query parameters
+//!
+//! - `limit`: Page size, between 1 and 100. Defaults to 20.
+//! - `cursor`: The `next_cursor` value from the previous page.
+//! - `contributor`: Only return issues featuring the contributor with this handle.
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{App, HttpServer};
+//! use nnmbackend::routes::issues;
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| App::new().service(issues::list_issues))
+//!         .bind("127.0.0.1:8080")?
+//!         .run()
+//!         .await
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! The catalog route returns an `ApiError`: `BadRequest` for an invalid limit or cursor and `Upstream`
+//! if the blob store or metadata repository cannot be read. The admin routes also return
+//! `Unauthorized` without a valid token, `Forbidden` without the required role, `NotFound` for an unknown issue, `BadRequest` with
+//! field-level `details` for invalid input, and `Replication` if not every region could be changed.
+
+use actix_multipart::form::{tempfile::TempFile, MultipartForm};
+use actix_web::web::{Data, Json, Path, Query};
+use serde_json::Value;
+
+use crate::{
+    auth::{Admin, Authorized, Editor},
+    error::ApiError,
+    routes::upload::{
+        check_fields, parse_contributors, replication_error, validate_uploaded_pdf, FieldError,
+    },
+    state::AppState,
+    utils::{
+        issues::{get_issue_catalog, CatalogQuery},
+        metadata::{IssueUpdate, MetadataError},
+        storage::{
+            get_issue_index, issue_key, pending_issue_key,
+            replicated::{copy_in_all_replicas, delete_from_all_replicas, put_to_all_replicas},
+        },
+    },
+};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct IssuesQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub contributor: Option<String>,
+}
+
+impl TryFrom<IssuesQuery> for CatalogQuery {
+    type Error = ApiError;
+
+    fn try_from(query: IssuesQuery) -> Result<Self, Self::Error> {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(ApiError::bad_request(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+        let cursor = query
+            .cursor
+            .map(|cursor| {
+                cursor
+                    .parse::<usize>()
+                    .map_err(|_| ApiError::bad_request(format!("Invalid cursor {:?}", cursor)))
+            })
+            .transpose()?;
+        Ok(CatalogQuery {
+            limit,
+            cursor,
+            contributor: query.contributor,
+        })
+    }
+}
+
+#[actix_web::get("/issues")]
+async fn list_issues(
+    query: Query<IssuesQuery>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let query = CatalogQuery::try_from(query.into_inner())?;
+    let snapshot = state.issue_cache.snapshot().await?;
+    let page = get_issue_catalog(
+        state.blob_store.as_ref(),
+        &snapshot,
+        &query,
+        state.config.storage.presign_ttl(),
+    )
+    .await;
+    Ok(actix_web::HttpResponse::Ok().json(page))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IssuePatch {
+    pub blurb: Option<String>,
+    pub contributors: Option<Value>,
+}
+
+impl TryFrom<IssuePatch> for IssueUpdate {
+    type Error = ApiError;
+
+    fn try_from(patch: IssuePatch) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+        if patch.blurb.is_none() && patch.contributors.is_none() {
+            return Err(ApiError::bad_request(
+                "Nothing to update; set blurb and/or contributors",
+            ));
+        }
+        if patch
+            .blurb
+            .as_ref()
+            .is_some_and(|blurb| blurb.trim().is_empty())
+        {
+            errors.push(FieldError::new("blurb", "must not be empty"));
+        }
+        let contributors = patch
+            .contributors
+            .map(|value| parse_contributors(&value))
+            .transpose()
+            .unwrap_or_else(|contributor_errors| {
+                errors.extend(contributor_errors);
+                None
+            });
+        check_fields("Invalid issue update", errors)?;
+        Ok(IssueUpdate {
+            blurb: patch.blurb,
+            contributors,
+        })
+    }
+}
+
+#[derive(Debug, MultipartForm)]
+struct ReplaceFileForm {
+    file: TempFile,
+}
+
+/// Whether the PDF for `issue_number` is in the store, checked directly rather than via the cache.
+async fn is_published(state: &AppState, issue_number: usize) -> Result<bool, ApiError> {
+    Ok(get_issue_index(state.blob_store.as_ref())
+        .await?
+        .iter()
+        .any(|issue| issue.number == issue_number))
+}
+
+#[actix_web::patch("/issues/{issue_number}")]
+async fn update_issue(
+    user: Authorized<Editor>,
+    issue_number: Path<usize>,
+    patch: Json<IssuePatch>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let issue_number = issue_number.into_inner();
+    let update = IssueUpdate::try_from(patch.into_inner())?;
+    let issue = state.metadata.update_issue(issue_number, update).await?;
+    log::info!(
+        "Issue {} updated by {} ({})",
+        issue_number,
+        user.username,
+        user.sub
+    );
+    state.issue_cache.invalidate().await;
+    Ok(actix_web::HttpResponse::Ok().json(issue))
+}
+
+#[actix_web::put("/issues/{issue_number}/file")]
+async fn replace_issue_file(
+    user: Authorized<Editor>,
+    issue_number: Path<usize>,
+    MultipartForm(form): MultipartForm<ReplaceFileForm>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let issue_number = issue_number.into_inner();
+    if !is_published(&state, issue_number).await? {
+        return Err(ApiError::NotFound(format!(
+            "Issue {} does not exist",
+            issue_number
+        )));
+    }
+    let errors: Vec<FieldError> =
+        validate_uploaded_pdf(&form.file, state.config.storage.max_upload_size())
+            .await?
+            .into_iter()
+            .collect();
+    check_fields("Invalid upload", errors)?;
+
+    // Stage and promote as `/upload` does, but never roll back the published PDF
+    let pending_key = pending_issue_key(issue_number);
+    let staged = put_to_all_replicas(&state.replicas, &pending_key, form.file.file.path()).await;
+    if !staged.is_complete() {
+        return Err(replication_error("Issue", &staged));
+    }
+    let report = copy_in_all_replicas(
+        &state.replicas,
+        &pending_key,
+        &issue_key(issue_number),
+        false,
+    )
+    .await;
+    let _ = delete_from_all_replicas(&state.replicas, &pending_key).await;
+    state.issue_cache.invalidate().await;
+    if !report.is_complete() {
+        return Err(replication_error("Issue", &report));
+    }
+    log::info!(
+        "Issue {} file replaced by {} ({})",
+        issue_number,
+        user.username,
+        user.sub
+    );
+
+    Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
+        "message": "Issue file replaced",
+        "key": report.key,
+        "replicas": report.replicas,
+    })))
+}
+
+#[actix_web::delete("/issues/{issue_number}")]
+async fn delete_issue(
+    user: Authorized<Admin>,
+    issue_number: Path<usize>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let issue_number = issue_number.into_inner();
+    let published = is_published(&state, issue_number).await?;
+
+    // Remove the PDF first, so a failure never leaves a readable PDF without metadata
+    if published {
+        if let Err(report) =
+            delete_from_all_replicas(&state.replicas, &issue_key(issue_number)).await
+        {
+            state.issue_cache.invalidate().await;
+            return Err(ApiError::Replication {
+                message: "Issue could not be deleted from every region; the metadata was kept"
+                    .to_string(),
+                details: serde_json::to_value(&report).ok(),
+            });
+        }
+    }
+    match state.metadata.delete_issue(issue_number).await {
+        Ok(()) => {}
+        Err(MetadataError::NotFound(_)) if published => {}
+        Err(e) => return Err(e.into()),
+    }
+    state.issue_cache.invalidate().await;
+    log::info!(
+        "Issue {} deleted by {} ({})",
+        issue_number,
+        user.username,
+        user.sub
+    );
+    Ok(actix_web::HttpResponse::NoContent().finish())
+}
@@ -1,6 +1,8 @@
+pub mod blob;
 pub mod dynamodb;
+pub mod issues;
 pub mod news;
+pub mod products;
 pub mod s3;
 pub mod shopify;
 pub mod upload;
-
@@ -1,53 +1,666 @@
+//! This module defines the routes for fetching and managing news articles.
+//!
+//! The routes are defined using Actix-web. Reading the news is public; previewing, creating,
+//! editing and deleting items requires an `Authorization: Bearer` token with the `news-editor`
+//! role. The public feed only shows items whose `status` is `published` and whose `published_at`
+//! time has passed, so editors can prepare drafts and schedule announcements ahead of time. Images
+//! are stored under `nnm_news/` in every regional bucket, and each response carries a signed
+//! `image_url`.
+//!
+//! # Routes
+//!
+//! The routes are mounted in a `/news` scope, which gets its own `multipart_config` limited to
+//! `NEWS_MULTIPART_LIMIT`, so an oversized image is rejected while it streams in rather than
+//! after it has been written to disk.
+//!
+//! - `GET /news?limit={n}&cursor={cursor}`: Fetches one page of live news articles, newest first.
+//!   An item whose image cannot be signed is still returned, with `image_url` set to `null`.
+//! - `GET /news/preview?limit={n}&cursor={cursor}`: Like `GET /news`, but includes drafts and scheduled items.
+//! - `POST /news`: Creates an item from a multipart form with `title`, `description`, an `image` file and
+//!   optionally `status` (`draft` or `published`, the default) and `publish_at` (RFC 3339, default now).
+//! - `PATCH /news/{id}`: Updates any of `title`, `description`, `image`, `status` and `publish_at`, sent as a multipart form.
+//!   Publishing a draft without a `publish_at` publishes it now, at the top of the feed.
+//! - `DELETE /news/{id}`: Deletes the item and, if it was uploaded through this API, its image.
+//!
+//! # Query parameters
+//!
+//! - `limit`: Page size, between 1 and 50. Defaults to 5.
+//! - `cursor`: The `next_cursor` value from the previous page.
+//!
+//! Items are ordered by `published_at`: the creation time, or the `publish_at` time of a scheduled item.
+//!
+//! # Structs
+//!
+//! - `NewsQuery`: The query parameters of `GET /news` and `GET /news/preview`.
+//! - `NewsAPIResponse`: Represents the structure of the response containing news articles and the
+//!   cursor for the next page, which is `null` on the last page.
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{web, App, HttpServer};
+//! use nnmbackend::{error::multipart_config, routes::news::{get_news, NEWS_MULTIPART_LIMIT}};
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| {
+//!         App::new().service(
+//!             web::scope("/news")
+//!                 .app_data(multipart_config(NEWS_MULTIPART_LIMIT))
+//!                 .service(get_news),
+//!         )
+//!     })
+//!     .bind("127.0.0.1:8080")?
+//!     .run()
+//!     .await
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! These routes return an `ApiError` with status `BadRequest` for an invalid limit or cursor, and `Upstream` if there is an issue reaching the
+//! database or S3 storage. The management routes also return `Unauthorized`, `Forbidden`,
+//! `NotFound` for an unknown id, `BadRequest` with field-level `details` for invalid input, and
+//! `Replication` if an image could not be stored in every region.
 
+use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
+use actix_web::web::{Data, Path, Query};
+use chrono::{DateTime, SubsecRound, Utc};
+use tokio::io::AsyncReadExt;
 
-/// This module defines the routes for fetching news articles.
-///
-/// The routes are defined using Actix-web and handle HTTP GET requests to fetch the latest news articles.
-///
-/// # Routes
-///
-/// - `GET /news`: Fetches the latest news articles from the database and S3 storage.
-///
-/// # Structs
-///
-/// - `NewsAPIResponse`: Represents the structure of the response containing news articles.
-///
-/// # Example
-///
-/// ```
-/// use actix_web::{web, App, HttpServer};
-/// use nnmbackend::routes::news::get_news;
-///
-/// #[actix_web::main]
-/// async fn main() -> std::io::Result<()> {
-///     HttpServer::new(|| {
-///         App::new()
-///             .route("/news", web::get().to(get_news))
-///     })
-///     .bind("127.0.0.1:8080")?
-///     .run()
-///     .await
-/// }
-/// ```
-///
-/// # Errors
-///
-/// This route returns an `InternalServerError` if there is an issue fetching the news articles from the database or S3 storage.
-use actix_web::Responder;
-
-use crate::utils::{dynamodb as db, news::{get_latest_news, NewsItem}, s3::get_s3_client};
+use crate::{
+    auth::{Authorized, NewsEditor},
+    error::ApiError,
+    routes::upload::{check_fields, replication_error, FieldError},
+    state::AppState,
+    utils::{
+        metadata::{NewNewsItem, NewsCursor, NewsFilter, NewsUpdate, LATEST_NEWS_LIMIT},
+        news::{
+            get_latest_news, news_image_key, sign_news_image, sniff_image_extension, NewsItem,
+            NewsStatus, NEWS_IMAGE_PREFIX,
+        },
+        storage::replicated::{delete_from_all_replicas, put_to_all_replicas},
+    },
+};
+
+const MAX_PAGE_SIZE: usize = 50;
+
+/// The largest news image accepted.
+const MAX_NEWS_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// The largest news form body: the largest image, plus room for the text fields.
+pub const NEWS_MULTIPART_LIMIT: usize = MAX_NEWS_IMAGE_SIZE + 64 * 1024;
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct NewsAPIResponse {
-    pub articles: Vec<NewsItem>
+    pub articles: Vec<NewsItem>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NewsQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+/// Validates the query and fetches one page of the items matching `filter`.
+async fn news_page(
+    query: NewsQuery,
+    filter: NewsFilter,
+    state: &AppState,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let limit = query.limit.unwrap_or(LATEST_NEWS_LIMIT);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let cursor = query
+        .cursor
+        .map(|cursor| cursor.parse::<NewsCursor>().map_err(ApiError::bad_request))
+        .transpose()?;
+
+    let page = get_latest_news(
+        state.metadata.as_ref(),
+        state.blob_store.as_ref(),
+        limit,
+        cursor,
+        filter,
+        state.config.storage.presign_ttl(),
+    )
+    .await?;
+    let response = NewsAPIResponse {
+        articles: page.items,
+        next_cursor: page.next_cursor.map(|cursor| cursor.to_string()),
+    };
+    Ok(actix_web::HttpResponse::Ok().json(response))
+}
+
+#[actix_web::get("")]
+pub async fn get_news(
+    query: Query<NewsQuery>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    news_page(query.into_inner(), NewsFilter::LiveAt(Utc::now()), &state).await
+}
+
+#[actix_web::get("/preview")]
+async fn preview_news(
+    _user: Authorized<NewsEditor>,
+    query: Query<NewsQuery>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    news_page(query.into_inner(), NewsFilter::All, &state).await
+}
+
+#[derive(Debug, MultipartForm)]
+struct CreateNewsForm {
+    title: Text<String>,
+    description: Text<String>,
+    image: TempFile,
+    status: Option<Text<String>>,
+    publish_at: Option<Text<String>>,
+}
+
+#[derive(Debug, MultipartForm)]
+struct UpdateNewsForm {
+    title: Option<Text<String>>,
+    description: Option<Text<String>>,
+    image: Option<TempFile>,
+    status: Option<Text<String>>,
+    publish_at: Option<Text<String>>,
+}
+
+fn parse_status(value: Option<&str>, errors: &mut Vec<FieldError>) -> Option<NewsStatus> {
+    value?
+        .parse()
+        .map_err(|e: String| errors.push(FieldError::new("status", e)))
+        .ok()
+}
+
+fn parse_publish_at(value: Option<&str>, errors: &mut Vec<FieldError>) -> Option<DateTime<Utc>> {
+    value?
+        .parse::<DateTime<Utc>>()
+        // Cursors carry milliseconds, so finer precision would make items unaddressable
+        .map(|publish_at| publish_at.trunc_subsecs(3))
+        .map_err(|_| {
+            errors.push(FieldError::new(
+                "publish_at",
+                "must be an RFC 3339 timestamp, such as 2025-01-31T17:00:00Z",
+            ))
+        })
+        .ok()
+}
+
+fn check_text(field: &str, value: &str, errors: &mut Vec<FieldError>) {
+    if value.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    }
+}
+
+/// Checks the size and type of an uploaded image, returning the extension to store it under.
+async fn check_image(image: &TempFile, errors: &mut Vec<FieldError>) -> Option<&'static str> {
+    if image.size > MAX_NEWS_IMAGE_SIZE {
+        errors.push(FieldError::new(
+            "image",
+            format!(
+                "is {} bytes, larger than the limit of {} bytes",
+                image.size, MAX_NEWS_IMAGE_SIZE
+            ),
+        ));
+        return None;
+    }
+    let mut header = Vec::with_capacity(12);
+    let read = match tokio::fs::File::open(image.file.path()).await {
+        Ok(file) => file.take(12).read_to_end(&mut header).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let extension = read.ok().and_then(|_| sniff_image_extension(&header));
+    if extension.is_none() {
+        errors.push(FieldError::new(
+            "image",
+            "must be a JPEG, PNG, GIF or WebP image",
+        ));
+    }
+    extension
+}
+
+/// Stores an uploaded image in every region under a fresh key, which is returned.
+async fn store_image(
+    state: &AppState,
+    image: &TempFile,
+    extension: &str,
+) -> Result<String, ApiError> {
+    let key = news_image_key(extension);
+    let report = put_to_all_replicas(&state.replicas, &key, image.file.path()).await;
+    if !report.is_complete() {
+        return Err(replication_error("Image", &report));
+    }
+    Ok(key)
+}
+
+/// Deletes an image this API uploaded. Images added by hand are left alone, since other items
+/// or pages may still use them.
+async fn delete_image(state: &AppState, key: &str) {
+    if key.starts_with(NEWS_IMAGE_PREFIX) {
+        let _ = delete_from_all_replicas(&state.replicas, key).await;
+    }
+}
+
+#[actix_web::post("")]
+async fn create_news(
+    user: Authorized<NewsEditor>,
+    MultipartForm(form): MultipartForm<CreateNewsForm>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let mut errors = Vec::new();
+    check_text("title", &form.title, &mut errors);
+    check_text("description", &form.description, &mut errors);
+    let status = parse_status(form.status.as_deref().map(String::as_str), &mut errors);
+    let publish_at = parse_publish_at(form.publish_at.as_deref().map(String::as_str), &mut errors);
+    let extension = check_image(&form.image, &mut errors).await;
+    check_fields("Invalid news item", errors)?;
+
+    // The image is stored first so the item never points at a missing object
+    let image_name = store_image(&state, &form.image, extension.unwrap_or_default()).await?;
+    let item = NewNewsItem {
+        title: form.title.0,
+        description: form.description.0,
+        image_name: image_name.clone(),
+        status: status.unwrap_or(NewsStatus::Published),
+        published_at: publish_at.unwrap_or_else(|| Utc::now().trunc_subsecs(3)),
+        publish_at,
+    };
+    let item = match state.metadata.create_news(item).await {
+        Ok(item) => item,
+        Err(e) => {
+            delete_image(&state, &image_name).await;
+            return Err(e.into());
+        }
+    };
+    log::info!(
+        "News item {} created by {} ({}, publishing at {})",
+        item.id,
+        user.username,
+        item.status,
+        item.published_at
+    );
+
+    let item = sign_news_image(
+        item,
+        state.blob_store.as_ref(),
+        state.config.storage.presign_ttl(),
+    )
+    .await;
+    Ok(actix_web::HttpResponse::Created().json(item))
+}
+
+#[actix_web::patch("/{id}")]
+async fn update_news(
+    user: Authorized<NewsEditor>,
+    id: Path<String>,
+    MultipartForm(form): MultipartForm<UpdateNewsForm>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let mut errors = Vec::new();
+    if let Some(title) = &form.title {
+        check_text("title", title, &mut errors);
+    }
+    if let Some(description) = &form.description {
+        check_text("description", description, &mut errors);
+    }
+    let status = parse_status(form.status.as_deref().map(String::as_str), &mut errors);
+    let publish_at = parse_publish_at(form.publish_at.as_deref().map(String::as_str), &mut errors);
+    let extension = match &form.image {
+        Some(image) => check_image(image, &mut errors).await,
+        None => None,
+    };
+    check_fields("Invalid news item", errors)?;
+
+    let mut update = NewsUpdate {
+        title: form.title.map(|title| title.0),
+        description: form.description.map(|description| description.0),
+        image_name: None,
+        status,
+        publish_at,
+    };
+    if update.is_empty() && form.image.is_none() {
+        return Err(ApiError::bad_request(
+            "Nothing to update; set title, description, image, status and/or publish_at",
+        ));
+    }
+
+    // Fail on an unknown id before uploading anything
+    let previous = state.metadata.get_news(&id).await?;
+    if let (Some(image), Some(extension)) = (&form.image, extension) {
+        update.image_name = Some(store_image(&state, image, extension).await?);
+    }
+    let new_image = update.image_name.clone();
+    let item = match state.metadata.update_news(&id, update).await {
+        Ok(item) => item,
+        Err(e) => {
+            if let Some(key) = &new_image {
+                delete_image(&state, key).await;
+            }
+            return Err(e.into());
+        }
+    };
+    if new_image.is_some() {
+        delete_image(&state, &previous.image_name).await;
+    }
+    log::info!("News item {} updated by {}", item.id, user.username);
+
+    let item = sign_news_image(
+        item,
+        state.blob_store.as_ref(),
+        state.config.storage.presign_ttl(),
+    )
+    .await;
+    Ok(actix_web::HttpResponse::Ok().json(item))
 }
 
-#[actix_web::get("/news")]
-pub async fn get_news() -> impl Responder {
-    let dbclient = db::get_db_client().await.unwrap();
-    let s3client = get_s3_client().await;
+#[actix_web::delete("/{id}")]
+async fn delete_news(
+    user: Authorized<NewsEditor>,
+    id: Path<String>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let item = state.metadata.get_news(&id).await?;
+    // Remove the item before its image, so the feed never links to a deleted image
+    state.metadata.delete_news(&id).await?;
+    delete_image(&state, &item.image_name).await;
+    log::info!("News item {} deleted by {}", id, user.username);
+    Ok(actix_web::HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{self, TestRequest},
+        web, App,
+    };
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        testing::{
+            app_state, bearer, Faults, FaultyRepository, FaultyStore, MultipartBody, RepoFaults,
+        },
+        utils::storage::replicated::Replica,
+    };
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    /// Sends `request` to the news routes, returning the status and the JSON body, if any.
+    async fn call(state: &AppState, request: TestRequest) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new().app_data(Data::new(state.clone())).service(
+                web::scope("/news")
+                    .app_data(crate::error::multipart_config(NEWS_MULTIPART_LIMIT))
+                    .service(get_news)
+                    .service(preview_news)
+                    .service(create_news)
+                    .service(update_news)
+                    .service(delete_news),
+            ),
+        )
+        .await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn create(body: MultipartBody) -> TestRequest {
+        body.attach(
+            TestRequest::post()
+                .uri("/news")
+                .insert_header(bearer(&["news-editor"])),
+        )
+    }
+
+    fn patch(id: &str, body: MultipartBody) -> TestRequest {
+        body.attach(
+            TestRequest::patch()
+                .uri(&format!("/news/{}", id))
+                .insert_header(bearer(&["news-editor"])),
+        )
+    }
+
+    fn item(title: &str) -> MultipartBody {
+        MultipartBody::new()
+            .text("title", title)
+            .text("description", "Details")
+            .file("image", "image.png", "image/png", PNG)
+    }
+
+    fn time(value: &Value) -> DateTime<Utc> {
+        value.as_str().unwrap().parse().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn publishing_a_draft_moves_it_to_now() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+        let (status, draft) = call(
+            &state,
+            create(
+                item("Draft")
+                    .text("status", "draft")
+                    .text("publish_at", "2024-01-01T00:00:00Z"),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, older) = call(
+            &state,
+            create(item("Older").text("publish_at", "2024-06-01T00:00:00Z")),
+        )
+        .await;
+
+        let before = Utc::now().trunc_subsecs(3);
+        let id = draft["id"].as_str().unwrap();
+        let (status, published) = call(
+            &state,
+            patch(id, MultipartBody::new().text("status", "published")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(published["status"], "published");
+        assert!(time(&published["published_at"]) >= before);
+        assert_eq!(published["publish_at"], Value::Null);
+
+        let (_, feed) = call(&state, TestRequest::get().uri("/news")).await;
+        let ids: Vec<_> = feed["articles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|article| article["id"].clone())
+            .collect();
+        assert_eq!(ids, [draft["id"].clone(), older["id"].clone()]);
+    }
 
-    let news_items = get_latest_news(&dbclient, &s3client).await.unwrap();
-    let response = NewsAPIResponse { articles: news_items };
-    actix_web::HttpResponse::Ok().json(response)
-}
\ No newline at end of file
+    /// The keys stored under `nnm_news/`.
+    async fn images(state: &AppState) -> Vec<String> {
+        let mut keys: Vec<String> = state
+            .blob_store
+            .list(NEWS_IMAGE_PREFIX)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|blob| blob.key)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[actix_web::test]
+    async fn managing_news_needs_the_news_editor_role() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+        let requests = || {
+            [
+                item("Title").attach(TestRequest::post().uri("/news")),
+                MultipartBody::new()
+                    .text("title", "Title")
+                    .attach(TestRequest::patch().uri("/news/1")),
+                TestRequest::delete().uri("/news/1"),
+                TestRequest::get().uri("/news/preview"),
+            ]
+        };
+        for request in requests() {
+            let (status, body) = call(&state, request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["code"], "unauthorized");
+        }
+        for request in requests() {
+            let (status, _) = call(&state, request.insert_header(bearer(&["editor"]))).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+        assert!(images(&state).await.is_empty());
+
+        let (status, _) = call(&state, TestRequest::get().uri("/news")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn invalid_items_are_rejected_field_by_field() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+
+        let body = MultipartBody::new()
+            .text("title", " ")
+            .text("description", "Details")
+            .text("status", "archived")
+            .text("publish_at", "tomorrow")
+            .file("image", "image.png", "image/png", b"not an image");
+        let (status, body) = call(&state, create(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Invalid news item");
+        let fields: Vec<_> = body["details"]["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["title", "status", "publish_at", "image"]);
+        assert!(images(&state).await.is_empty());
+
+        let (_, created) = call(&state, create(item("Launch"))).await;
+        let id = created["id"].as_str().unwrap();
+        // A form with none of the known fields
+        let body = MultipartBody::new().text("colour", "blue");
+        let (status, body) = call(&state, patch(id, body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Nothing to update"));
+        let (status, body) = call(
+            &state,
+            patch(id, MultipartBody::new().text("description", "")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["details"]["errors"],
+            json!([{ "field": "description", "message": "must not be empty" }])
+        );
+    }
+
+    #[actix_web::test]
+    async fn unknown_ids_are_not_found() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+
+        let body = MultipartBody::new().file("image", "image.png", "image/png", PNG);
+        let (status, body) = call(&state, patch("404", body)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+        // The id is checked before the new image is uploaded
+        assert!(images(&state).await.is_empty());
+
+        let request = TestRequest::delete()
+            .uri("/news/404")
+            .insert_header(bearer(&["news-editor"]));
+        let (status, _) = call(&state, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn a_failed_update_deletes_the_new_image() {
+        let root = tempfile::tempdir().unwrap();
+        let mut state = app_state(root.path());
+        state.metadata = Arc::new(FaultyRepository::new(RepoFaults {
+            update_news: true,
+            ..RepoFaults::default()
+        }));
+        let (_, created) = call(&state, create(item("Launch"))).await;
+        let old_image = images(&state).await;
+        assert_eq!(old_image.len(), 1);
+
+        let id = created["id"].as_str().unwrap();
+        let body = MultipartBody::new().file("image", "new.png", "image/png", PNG);
+        let (status, body) = call(&state, patch(id, body)).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "upstream_error");
+        assert_eq!(images(&state).await, old_image);
+        assert_eq!(
+            state.metadata.get_news(id).await.unwrap().image_name,
+            old_image[0]
+        );
+    }
+
+    #[actix_web::test]
+    async fn a_replaced_or_deleted_item_takes_its_image_along() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+        let (_, created) = call(&state, create(item("Launch"))).await;
+        let id = created["id"].as_str().unwrap();
+
+        let body = MultipartBody::new().file("image", "new.png", "image/png", PNG);
+        let (status, updated) = call(&state, patch(id, body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(updated["image_name"], created["image_name"]);
+        assert_eq!(
+            images(&state).await,
+            [updated["image_name"].as_str().unwrap()]
+        );
+
+        let request = TestRequest::delete()
+            .uri(&format!("/news/{}", id))
+            .insert_header(bearer(&["news-editor"]));
+        let (status, _) = call(&state, request).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(images(&state).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn an_image_that_cannot_be_replicated_is_reported_as_an_image() {
+        let root = tempfile::tempdir().unwrap();
+        let mut state = app_state(root.path());
+        let faults = Faults {
+            put: true,
+            ..Faults::default()
+        };
+        state.replicas = vec![Replica {
+            region: "local".to_string(),
+            store: Arc::new(FaultyStore::new(state.blob_store.clone(), faults)),
+        }];
+
+        let (status, body) = call(&state, create(item("Launch"))).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body["message"],
+            "Image could not be stored in every region and was rolled back"
+        );
+        let (_, feed) = call(&state, TestRequest::get().uri("/news")).await;
+        assert_eq!(feed["articles"], json!([]));
+    }
+}
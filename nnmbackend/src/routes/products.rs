@@ -0,0 +1,107 @@
+//! This module defines the routes for reading the Shopify product catalog.
+//!
+//! The routes are defined using Actix-web and proxy the Storefront API, so the merch page shows
+//! current stock and prices without waiting for a site rebuild. Results are cached in memory for
+//! `cache.product_ttl_secs`, so a product can be up to that many seconds out of date.
+//!
+//! # Routes
+//!
+//! - `GET /products?limit={n}&cursor={cursor}`: Fetches one page of products, with their variants,
+//!   prices, availability and images.
+//! - `GET /products/{handle}`: Fetches a single product by its handle.
+//!
+//! # Query parameters
+//!
+//! - `limit`: Page size, between 1 and 50. Defaults to 20.
+//! - `cursor`: The `next_cursor` value from the previous page.
+//!
+//! # Structs
+//!
+//! - `ProductsQuery`: The query parameters of `GET /products`.
+//! - `ProductsAPIResponse`: Represents the structure of the response containing products and the
+//!   cursor for the next page, which is `null` on the last page.
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{web, App, HttpServer};
+//! use nnmbackend::routes::products;
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| {
+//!         App::new()
+//!             .service(products::get_products)
+//!             .service(products::get_product)
+//!     })
+//!     .bind("127.0.0.1:8080")?
+//!     .run()
+//!     .await
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! These routes return an `ApiError`: `BadRequest` for an invalid limit or cursor, `NotFound` for an
+//! unknown handle, and `Upstream` if Shopify cannot be reached, keeps failing or returns errors.
+
+use actix_web::web::{Data, Path, Query};
+
+use crate::{error::ApiError, state::AppState, utils::shopify::graphql::types::Product};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductsAPIResponse {
+    pub products: Vec<Product>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ProductsQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+#[actix_web::get("/products")]
+async fn get_products(
+    query: Query<ProductsQuery>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let ProductsQuery { limit, cursor } = query.into_inner();
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    if cursor.as_deref().is_some_and(str::is_empty) {
+        return Err(ApiError::bad_request("cursor must not be empty"));
+    }
+
+    let page = state.product_cache.products(limit, cursor).await?;
+    let response = ProductsAPIResponse {
+        products: page.nodes.clone(),
+        next_cursor: page
+            .page_info
+            .has_next_page
+            .then(|| page.page_info.end_cursor.clone())
+            .flatten(),
+    };
+    Ok(actix_web::HttpResponse::Ok().json(response))
+}
+
+#[actix_web::get("/products/{handle}")]
+async fn get_product(
+    handle: Path<String>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    let product = state
+        .product_cache
+        .product(&handle)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Product {} does not exist", handle)))?;
+    Ok(actix_web::HttpResponse::Ok().json(product.as_ref()))
+}
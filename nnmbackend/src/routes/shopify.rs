@@ -1,139 +1,156 @@
-/// This module defines the routes for handling Shopify checkout operations.
-///
-/// The routes are defined using Actix-web and handle various operations related to Shopify checkouts,
-/// including creating a checkout session, adding items to a checkout, and retrieving checkout details.
-///
-/// # Routes
-///
-/// - `GET /create_checkout`: Creates a new checkout session.
-/// - `POST /request_checkout`: Requests a new checkout and adds items to it.
-/// - `GET /checkout/{checkout_id}`: Retrieves the details of an existing checkout session.
-///
-/// # Example
-///
-/// ```
-/// use actix_web::{web, App, HttpServer};
-/// use nnmbackend::routes::shopify;
-///
-/// #[actix_web::main]
-/// async fn main() -> std::io::Result<()> {
-///     HttpServer::new(|| {
-///         App::new()
-///             .service(shopify::create_checkout)
-///             .service(shopify::execute_checkout)
-///             .service(shopify::get_checkout)
-///     })
-///     .bind("127.0.0.1:8080")?
-///     .run()
-///     .await
-/// }
-/// ```
-///
-/// # Errors
-///
-/// These routes return an `InternalServerError` if there is an issue processing the Shopify request.
-/// They may also return a `BadRequest` if there are user errors in the GraphQL response.
-use actix_web::web::{Json, Path};
+//! This module defines the routes for handling Shopify checkout operations.
+//!
+//! The routes are defined using Actix-web and handle various operations related to Shopify checkouts,
+//! including creating a checkout session, adding items to a new or existing checkout, changing the quantities of its lines or
+//! removing them, and retrieving checkout details.
+//!
+//! # Routes
+//!
+//! - `GET /create_checkout`: Creates a new checkout session.
+//! - `POST /request_checkout`: Creates a new checkout holding the given items (at least one), in a single `cartCreate` call.
+//! - `GET /checkout/{checkout_id}`: Retrieves the details of an existing checkout session.
+//! - `POST /checkout/{checkout_id}/lines/add`: Adds the given items to an existing checkout.
+//! - `POST /checkout/{checkout_id}/lines/update`: Sets the quantity of each given line, by `line_id`. A quantity
+//!   of 0 removes the line.
+//! - `POST /checkout/{checkout_id}/lines/remove`: Removes each line in `line_ids`.
+//!
+//! The line routes take at least one line and return the updated cart: `lines/add` takes a `MultiItemPayload`,
+//! `lines/update` a `MultiLineUpdatePayload` (`{"items": [{"line_id", "quantity"}]}`) and `lines/remove` a
+//! `LineRemovalPayload` (`{"line_ids": [..]}`).
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{web, App, HttpServer};
+//! use nnmbackend::routes::shopify;
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| {
+//!         App::new()
+//!             .service(shopify::create_checkout)
+//!             .service(shopify::execute_checkout)
+//!             .service(shopify::get_checkout)
+//!             .service(shopify::add_checkout_lines)
+//!             .service(shopify::update_checkout_lines)
+//!             .service(shopify::remove_checkout_lines)
+//!     })
+//!     .bind("127.0.0.1:8080")?
+//!     .run()
+//!     .await
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! These routes return an `ApiError`: `BadRequest` if `request_checkout` or a line route is given no items, `Unprocessable` (422) with the `userErrors` as details if Shopify
+//! rejects the request, and `Upstream` (502) if Shopify cannot be reached, keeps throttling or failing
+//! after `shopify.max_attempts` tries, or returns GraphQL errors or something unusable.
 
-use crate::utils::shopify::{
-    add_items_mutation, create_cart_mutation, get_cart_query, payloads::MultiItemPayload,
-    send_shopify_request, FullAddItemResponse, FullCartCreateResponse, FullCartGetResponse,
+use actix_web::web::{Data, Json, Path};
+
+use crate::{
+    error::ApiError,
+    state::AppState,
+    utils::shopify::{
+        add_items_mutation, create_cart_mutation, get_cart_query,
+        payloads::{LineRemovalPayload, MultiItemPayload, MultiLineUpdatePayload},
+        remove_lines_mutation, update_lines_mutation, AddItemResponse, CartCreateResponse,
+        CartGetAPIResponse, RemoveLinesResponse, UpdateLinesResponse,
+    },
 };
 
 #[actix_web::get("/create_checkout")]
-async fn create_checkout() -> actix_web::HttpResponse {
+async fn create_checkout(state: Data<AppState>) -> Result<actix_web::HttpResponse, ApiError> {
     // Create a checkout session
-    let payload = create_cart_mutation();
+    let payload = create_cart_mutation(None);
 
-    let res = send_shopify_request(payload.to_payload()).await;
-    match res {
-        Ok(res) => {
-            let body = res.text().await.unwrap();
-            let parsed: FullCartCreateResponse = serde_json::from_str(&body).unwrap();
-            let parsed = parsed.data.cart_create;
-            if let Some(errors) = parsed.user_errors {
-                if !errors.is_empty() {
-                    // Send back GraphQL errors if there are any
-                    return actix_web::HttpResponse::BadRequest().body(format!(
-                        "{{\"error\": \"Error creating checkout session: {:?}\"}}",
-                        errors
-                    ));
-                }
-            }
-            actix_web::HttpResponse::Ok()
-                .body(serde_json::to_string(&parsed.cart).unwrap())
-        }
-        Err(e) => {
-            actix_web::HttpResponse::InternalServerError().body(format!(
-                "{{\"error\": \"Error creating checkout session: {:?}\"}}",
-                e
-            ))
-        }
-    }
+    let parsed: CartCreateResponse = state.shopify.execute(&payload).await?;
+    Ok(actix_web::HttpResponse::Ok().json(parsed.cart_create.cart))
 }
 
 #[actix_web::post("/request_checkout")]
-async fn execute_checkout(Json(payload): Json<MultiItemPayload>) -> actix_web::HttpResponse {
-    // First, request a new checkout
-    let request = create_cart_mutation();
-    let res = send_shopify_request(request.to_payload()).await;
-    if let Err(e) = res {
-        return actix_web::HttpResponse::InternalServerError().body(format!(
-            "{{\"error\": \"Error creating checkout session: {:?}\"}}",
-            e
-        ));
-    }
-    let body = res.unwrap().text().await.unwrap();
-    let parsed: FullCartCreateResponse = serde_json::from_str(&body).unwrap();
-    let parsed = parsed.data.cart_create;
-    if let Some(errors) = parsed.user_errors {
-        if !errors.is_empty() {
-            // Send back GraphQL errors if there are any
-            return actix_web::HttpResponse::BadRequest().body(format!(
-                "{{\"error\": \"Error creating checkout session: {:?}\"}}",
-                errors
-            ));
-        }
-    }
-    let cart_id = parsed.cart.id.strip_prefix("gid://shopify/Cart/").unwrap();
+async fn execute_checkout(
+    Json(payload): Json<MultiItemPayload>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    check_lines(&payload.items)?;
+    // Create the checkout with its lines in one call
+    let request = create_cart_mutation(Some(&payload));
 
-    let request = add_items_mutation(cart_id, &payload);
-    println!("[line 146]: {}", request.to_payload());
+    let parsed: CartCreateResponse = state.shopify.execute(&request).await?;
+    Ok(actix_web::HttpResponse::Ok().json(parsed.cart_create.cart))
+}
 
-    if let Ok(res) = send_shopify_request(request.to_payload()).await {
-        let body = res.text().await.unwrap();
-        println!("[line 149]: {}", body);
-        let parsed: FullAddItemResponse = serde_json::from_str(&body).unwrap();
-        let Some(parsed) = parsed.data.add_item else {
-            // Send back GraphQL errors if there are any
-            return actix_web::HttpResponse::BadRequest()
-                .body(format!("{{\"error\": \"Error: {:?}\" }}", parsed.errors));
-        };
-        if let Some(parsederrs) = parsed.user_errors {
-            if !parsederrs.is_empty() {
-                return actix_web::HttpResponse::BadRequest()
-                    .body(format!("{{\"error\": \"Error: {:?}\" }}", parsederrs));
-            }
-        }
-        actix_web::HttpResponse::Ok().body(serde_json::to_string(&parsed.cart).unwrap())
-    } else {
-        actix_web::HttpResponse::InternalServerError()
-            .body("{ \"error\": \"Error adding item to cart\" }")
-    }
+#[actix_web::post("/checkout/{checkout_id}/lines/add")]
+async fn add_checkout_lines(
+    checkout_id: Path<String>,
+    Json(payload): Json<MultiItemPayload>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    check_lines(&payload.items)?;
+    let request = add_items_mutation(&checkout_id, &payload);
+
+    let parsed: AddItemResponse = state.shopify.execute(&request).await?;
+    let cart = parsed
+        .add_item
+        .and_then(|added| added.cart)
+        .ok_or_else(|| ApiError::Upstream("Shopify did not return the updated cart".to_string()))?;
+    Ok(actix_web::HttpResponse::Ok().json(cart))
 }
 
 #[actix_web::get("/checkout/{checkout_id}")]
-async fn get_checkout(checkout_id: Path<String>) -> actix_web::HttpResponse {
+async fn get_checkout(
+    checkout_id: Path<String>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
     // Get the checkout session
     let get_checkout_query = get_cart_query(&checkout_id);
 
-    if let Ok(res) = send_shopify_request(get_checkout_query.to_payload()).await {
-        let body = res.text().await.unwrap();
-        let parsed: FullCartGetResponse = serde_json::from_str(&body).unwrap();
-        let parsed = parsed.data.cart;
-        actix_web::HttpResponse::Ok().body(serde_json::to_string(&parsed).unwrap())
-    } else {
-        actix_web::HttpResponse::InternalServerError()
-            .body("{ \"error\": \"Error getting checkout session\" }")
+    let parsed: CartGetAPIResponse = state.shopify.execute(&get_checkout_query).await?;
+    Ok(actix_web::HttpResponse::Ok().json(parsed.cart))
+}
+
+fn check_lines<T>(items: &[T]) -> Result<(), ApiError> {
+    if items.is_empty() {
+        return Err(ApiError::BadRequest {
+            message: "No cart lines given".to_string(),
+            details: None,
+        });
     }
+    Ok(())
+}
+
+#[actix_web::post("/checkout/{checkout_id}/lines/update")]
+async fn update_checkout_lines(
+    checkout_id: Path<String>,
+    Json(payload): Json<MultiLineUpdatePayload>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    check_lines(&payload.items)?;
+    let request = update_lines_mutation(&checkout_id, &payload);
+
+    let parsed: UpdateLinesResponse = state.shopify.execute(&request).await?;
+    let cart = parsed
+        .update_lines
+        .and_then(|updated| updated.cart)
+        .ok_or_else(|| ApiError::Upstream("Shopify did not return the updated cart".to_string()))?;
+    Ok(actix_web::HttpResponse::Ok().json(cart))
+}
+
+#[actix_web::post("/checkout/{checkout_id}/lines/remove")]
+async fn remove_checkout_lines(
+    checkout_id: Path<String>,
+    Json(payload): Json<LineRemovalPayload>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    check_lines(&payload.line_ids)?;
+    let request = remove_lines_mutation(&checkout_id, &payload);
+
+    let parsed: RemoveLinesResponse = state.shopify.execute(&request).await?;
+    let cart = parsed
+        .remove_lines
+        .and_then(|removed| removed.cart)
+        .ok_or_else(|| ApiError::Upstream("Shopify did not return the updated cart".to_string()))?;
+    Ok(actix_web::HttpResponse::Ok().json(cart))
 }
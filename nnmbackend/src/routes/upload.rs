@@ -1,95 +1,721 @@
-use std::io::Read;
+//! This module defines the routes for handling file uploads.
+//!
+//! The routes are defined using Actix-web and handle multipart form data for file uploads.
+//!
+//! # Routes
+//!
+//! - `POST /upload`: Handles file uploads with additional metadata.
+//!
+//! # Structs
+//!
+//! - `UploadForm`: Represents the structure of the multipart form data for file uploads.
+//!
+//! # Example
+//!
+//! ```
+//! use actix_web::{web, App, HttpServer};
+//! use nnmbackend::routes::upload;
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     HttpServer::new(|| {
+//!         App::new()
+//!             .route("/upload", web::post().to(upload))
+//!     })
+//!     .bind("127.0.0.1:8080")?
+//!     .run()
+//!     .await
+//! }
+//! ```
+//!
+//! # Validation
+//!
+//! Nothing is stored unless the whole form is valid. The file must start with the `%PDF-` magic
+//! bytes, parse as a PDF with at least one page and fit within `storage.max_upload_mib`. The
+//! `contributors` field must be a JSON array of `{ "name": ..., "handle": ... }` objects with
+//! non-empty strings. An issue number that is already published is rejected unless the form
+//! sets `overwrite` to `true`.
+//!
+//! Validation failures are reported together, one entry per problem:
+//!
+//! ```json
+//! { "code": "bad_request", "message": "Invalid upload", "details": { "errors": [{ "field": "contributors[0].handle", "message": "is required" }] } }
+//! ```
+//!
+//! # Consistency
+//!
+//! The PDF is first staged under `nnm_pending/` in every region, then the metadata record is
+//! written, and only then is the PDF copied to `nnm_issues/`, where readers can see it. If the
+//! metadata write fails, the staged copies are deleted; if promotion fails, the previous
+//! metadata record is restored. Either way no reader sees a PDF without its metadata.
+//!
+//! # Errors
+//!
+//! This route returns an `ApiError`: `Unauthorized` unless the request carries a valid
+//! `Authorization: Bearer` access token, `Forbidden` unless the user has the `editor` role,
+//! `BadRequest` if the form data is malformed or invalid, `Conflict` if the issue already exists,
+//! `Replication` if the PDF could not be stored in
+//! every regional bucket, and `Upstream` if storing the metadata fails.
+//!
+//! On success the response lists the outcome for each region:
+//!
+//! ```json
+//! { "message": "Issue uploaded and added to database", "key": "nnm_issues/issue_12.pdf", "replicas": [{ "region": "us-east-1", "stored": true, "rolled_back": false, "error": null }] }
+//! ```
+
+use std::{io::Read, path::Path};
 
 use actix_multipart::form::{tempfile::TempFile, MultipartForm};
-use actix_web::Responder;
-
-use crate::utils::{cognito::*, dynamodb::{self as db, DBContributor}, s3::{get_bucket_for_client, get_s3_client}};
-
-/// This module defines the routes for handling file uploads.
-///
-/// The routes are defined using Actix-web and handle multipart form data for file uploads.
-///
-/// # Routes
-///
-/// - `POST /upload`: Handles file uploads with additional metadata.
-///
-/// # Structs
-///
-/// - `UploadForm`: Represents the structure of the multipart form data for file uploads.
-///
-/// # Example
-///
-/// ```
-/// use actix_web::{web, App, HttpServer};
-/// use nnmbackend::routes::upload;
-///
-/// #[actix_web::main]
-/// async fn main() -> std::io::Result<()> {
-///     HttpServer::new(|| {
-///         App::new()
-///             .route("/upload", web::post().to(upload))
-///     })
-///     .bind("127.0.0.1:8080")?
-///     .run()
-///     .await
-/// }
-/// ```
-///
-/// # Errors
-///
-/// This route returns an `InternalServerError` if there is an issue processing the uploaded file or form data.
+use actix_web::web::Data;
+use serde_json::Value;
+
+use crate::{
+    auth::{Authorized, Editor},
+    error::ApiError,
+    state::AppState,
+    utils::{
+        dynamodb::{self as db, DBContributor},
+        metadata::MetadataError,
+        storage::{
+            get_issue_index, issue_key, pending_issue_key,
+            replicated::{
+                copy_in_all_replicas, delete_from_all_replicas, put_to_all_replicas,
+                ReplicationReport,
+            },
+        },
+    },
+};
 
 #[derive(Debug, MultipartForm)]
 struct UploadForm {
     file: TempFile,
-    access_token: actix_multipart::form::text::Text<String>,
     issue_number: actix_multipart::form::text::Text<usize>,
     blurb: actix_multipart::form::text::Text<String>,
     contributors: actix_multipart::form::text::Text<String>,
+    overwrite: Option<actix_multipart::form::text::Text<bool>>,
+}
+
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// A problem with one field of a form or request body.
+#[derive(Debug, Clone, serde::Serialize)]
+pub(crate) struct FieldError {
+    field: String,
+    message: String,
+}
+
+impl FieldError {
+    pub(crate) fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Reports `errors` as a single `BadRequest`, or nothing if there are none.
+pub(crate) fn check_fields(message: &str, errors: Vec<FieldError>) -> Result<(), ApiError> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::BadRequest {
+        message: message.to_string(),
+        details: Some(serde_json::json!({ "errors": errors })),
+    })
+}
+
+/// Checks that the file at `path` is a PDF that parses and is no larger than `max_size` bytes.
+fn validate_pdf(path: &Path, size: usize, max_size: usize) -> Option<FieldError> {
+    if size == 0 {
+        return Some(FieldError::new("file", "is empty"));
+    }
+    if size > max_size {
+        return Some(FieldError::new(
+            "file",
+            format!(
+                "is {} bytes, larger than the limit of {} bytes",
+                size, max_size
+            ),
+        ));
+    }
+
+    let mut magic = [0u8; 5];
+    let read = std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic));
+    if read.is_err() || magic != PDF_MAGIC {
+        return Some(FieldError::new("file", "is not a PDF"));
+    }
+
+    match lopdf::Document::load_metadata(path) {
+        Ok(metadata) if metadata.page_count > 0 => None,
+        Ok(_) => Some(FieldError::new("file", "is a PDF with no pages")),
+        Err(e) => Some(FieldError::new(
+            "file",
+            format!("could not be parsed as a PDF: {}", e),
+        )),
+    }
+}
+
+fn required_string(
+    contributor: &serde_json::Map<String, Value>,
+    index: usize,
+    name: &str,
+    errors: &mut Vec<FieldError>,
+) -> String {
+    let field = format!("contributors[{}].{}", index, name);
+    match contributor.get(name) {
+        Some(Value::String(value)) if !value.trim().is_empty() => value.clone(),
+        None | Some(Value::Null) => {
+            errors.push(FieldError::new(field, "is required"));
+            String::new()
+        }
+        Some(Value::String(_)) => {
+            errors.push(FieldError::new(field, "must not be empty"));
+            String::new()
+        }
+        Some(_) => {
+            errors.push(FieldError::new(field, "must be a string"));
+            String::new()
+        }
+    }
+}
+
+/// Validates an uploaded temp file on the blocking thread pool, since parsing reads the file.
+pub(crate) async fn validate_uploaded_pdf(
+    file: &TempFile,
+    max_size: usize,
+) -> Result<Option<FieldError>, ApiError> {
+    let path = file.file.path().to_path_buf();
+    let size = file.size;
+    actix_web::web::block(move || validate_pdf(&path, size, max_size))
+        .await
+        .map_err(|e| ApiError::Internal(format!("Could not validate uploaded file: {}", e)))
+}
+
+/// Parses the `contributors` field, reporting every problem rather than just the first.
+pub(crate) fn parse_contributors(value: &Value) -> Result<Vec<DBContributor>, Vec<FieldError>> {
+    let Value::Array(items) = value else {
+        return Err(vec![FieldError::new(
+            "contributors",
+            "must be a JSON array",
+        )]);
+    };
+
+    let mut errors = Vec::new();
+    let mut contributors = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let Value::Object(contributor) = item else {
+            errors.push(FieldError::new(
+                format!("contributors[{}]", index),
+                "must be an object with `name` and `handle`",
+            ));
+            continue;
+        };
+        contributors.push(DBContributor {
+            name: required_string(contributor, index, "name", &mut errors),
+            handle: required_string(contributor, index, "handle", &mut errors),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(contributors)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Reports a replicated write that did not reach every region. `subject` names what was being
+/// stored, such as `Issue` or `Image`, and starts the message.
+pub(crate) fn replication_error(subject: &str, report: &ReplicationReport) -> ApiError {
+    let message = if report.is_inconsistent() {
+        format!(
+            "{} could not be stored in every region, and rolling back failed; regional buckets are inconsistent",
+            subject
+        )
+    } else {
+        format!(
+            "{} could not be stored in every region and was rolled back",
+            subject
+        )
+    };
+    ApiError::Replication {
+        message,
+        details: serde_json::to_value(report).ok(),
+    }
+}
+
+/// Puts back the record an upload replaced, or removes the record it created.
+async fn restore_metadata(state: &AppState, issue_number: usize, previous: Option<db::DBIssue>) {
+    let restored = match previous {
+        Some(issue) => state.metadata.put_issue(issue).await,
+        None => state.metadata.delete_issue(issue_number).await,
+    };
+    if let Err(e) = restored {
+        log::error!(
+            "Could not restore metadata for issue {} after a failed upload: {}",
+            issue_number,
+            e
+        );
+    }
 }
 
 /// This route allows for uploading a new issue to the NNM database.
 #[actix_web::post("/upload")]
-async fn upload(MultipartForm(form): MultipartForm<UploadForm>) -> impl Responder {
-    // Check access token
-    let client = get_cognito_client().await;
-    let token = form.access_token.0.clone();
-    if !validate_token(client, token).await {
-        return actix_web::HttpResponse::Unauthorized().body("Invalid access token");
+async fn upload(
+    user: Authorized<Editor>,
+    MultipartForm(form): MultipartForm<UploadForm>,
+    state: Data<AppState>,
+) -> Result<actix_web::HttpResponse, ApiError> {
+    // Validate the whole form before anything is written
+    let issue_number = form.issue_number.0;
+    let mut errors = Vec::new();
+    if issue_number == 0 {
+        errors.push(FieldError::new("issue_number", "must be at least 1"));
     }
+    errors.extend(validate_uploaded_pdf(&form.file, state.config.storage.max_upload_size()).await?);
+    let contributors = match serde_json::from_str::<Value>(&form.contributors.0) {
+        Ok(value) => parse_contributors(&value).unwrap_or_else(|contributor_errors| {
+            errors.extend(contributor_errors);
+            Vec::new()
+        }),
+        Err(e) => {
+            errors.push(FieldError::new(
+                "contributors",
+                format!("is not valid JSON: {}", e),
+            ));
+            Vec::new()
+        }
+    };
+    check_fields("Invalid upload", errors)?;
 
-    // Get file as bytes
-    let mut file = form.file.file.as_file();
-    let mut bytes = Vec::new();
-    file.read_to_end(&mut bytes).unwrap();
+    // Check the store itself rather than the issue cache, which may be stale
+    let overwrite = form.overwrite.as_ref().is_some_and(|overwrite| overwrite.0);
+    let published = get_issue_index(state.blob_store.as_ref())
+        .await?
+        .iter()
+        .any(|issue| issue.number == issue_number);
+    if published && !overwrite {
+        return Err(ApiError::Conflict(format!(
+            "Issue {} already exists; set overwrite to replace it",
+            issue_number
+        )));
+    }
 
-    // Upload the issue to S3
-    let s3client = get_s3_client().await;
-    let issue_number = form.issue_number.0;
-    let issue_key = format!("nnm_issues/issue_{}.pdf", issue_number);
+    // Stage the issue in every region under a key that no reader lists.
+    // The temp file is streamed to each bucket rather than read into memory
+    let pending_key = pending_issue_key(issue_number);
+    let staged = put_to_all_replicas(&state.replicas, &pending_key, form.file.file.path()).await;
+    if !staged.is_complete() {
+        return Err(replication_error("Issue", &staged));
+    }
+
+    // Add the issue to the database, remembering the record it replaces
+    let previous = match state.metadata.get_issue(issue_number).await {
+        Ok(issue) => Some(issue),
+        Err(MetadataError::NotFound(_)) => None,
+        Err(e) => {
+            let _ = delete_from_all_replicas(&state.replicas, &pending_key).await;
+            return Err(e.into());
+        }
+    };
+    let issue = db::DBIssue::new(issue_number, form.blurb.0.clone(), contributors);
+    if let Err(e) = state.metadata.put_issue(issue).await {
+        let _ = delete_from_all_replicas(&state.replicas, &pending_key).await;
+        return Err(e.into());
+    }
+
+    // Promote the staged copy. If an older PDF was published, a failed promotion must not
+    // delete it, so it is only rolled back for new issues
+    let report = copy_in_all_replicas(
+        &state.replicas,
+        &pending_key,
+        &issue_key(issue_number),
+        !published,
+    )
+    .await;
+    let _ = delete_from_all_replicas(&state.replicas, &pending_key).await;
+    if !report.is_complete() {
+        restore_metadata(&state, issue_number, previous).await;
+        state.issue_cache.invalidate().await;
+        return Err(replication_error("Issue", &report));
+    }
+    state.issue_cache.invalidate().await;
+    log::info!(
+        "Issue {} uploaded by {} ({})",
+        issue_number,
+        user.username,
+        user.sub
+    );
 
-    // Need to upload to buckets in both regions
-    let bucket = get_bucket_for_client(&s3client);
+    Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
+        "message": "Issue uploaded and added to database",
+        "key": report.key,
+        "replicas": report.replicas,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{
+        http::StatusCode,
+        test::{self, TestRequest},
+        App,
+    };
+    use lopdf::{dictionary, Document, Object};
+    use serde_json::json;
 
-    let put_req = s3client
-        .put_object()
-        .bucket(bucket)
-        .key(issue_key)
-        .body(bytes.into());
+    use super::*;
+    use crate::{
+        testing::{
+            app_state, bearer, Faults, FaultyRepository, FaultyStore, MultipartBody, RepoFaults,
+        },
+        utils::storage::{
+            replicated::{Replica, ReplicaStatus},
+            ISSUE_PREFIX, PENDING_PREFIX,
+        },
+    };
 
-    let res = put_req.send().await;
-    if let Err(e) = res {
-        return actix_web::HttpResponse::InternalServerError().body(format!("Error: {}", e));
+    /// A minimal PDF with `pages` blank pages.
+    fn pdf(pages: usize) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let kids: Vec<Object> = (0..pages)
+            .map(|_| {
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                    })
+                    .into()
+            })
+            .collect();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => pages as i64,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        bytes
     }
 
-    // Add the issue to the database
-    // Parse contributors
-    let contributors: Vec<DBContributor> = serde_json::from_str(&form.contributors.0).unwrap();
-    let issue = db::DBIssue::new(issue_number, form.blurb.0.clone(), contributors);
-    let put_res = db::put_issue_data(issue, &db::get_db_client().await.unwrap()).await;
-    if let Err(e) = put_res {
-        return actix_web::HttpResponse::InternalServerError().body(format!("Error: {}", e));
+    fn pairs(errors: &[FieldError]) -> Vec<(&str, &str)> {
+        errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.message.as_str()))
+            .collect()
+    }
+
+    /// Writes `bytes` to a file and validates it with a limit of `max_size` bytes.
+    fn validate(bytes: &[u8], max_size: usize) -> Option<FieldError> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), bytes).unwrap();
+        validate_pdf(file.path(), bytes.len(), max_size)
     }
 
-    actix_web::HttpResponse::Ok().body("Issue uploaded and added to database")
-}
\ No newline at end of file
+    #[test]
+    fn accepts_a_pdf_with_pages() {
+        assert!(validate(&pdf(2), 1024 * 1024).is_none());
+    }
+
+    #[test]
+    fn rejects_files_without_the_pdf_magic_bytes() {
+        let error = validate(b"<html>not a pdf</html>", 1024).unwrap();
+        assert_eq!(pairs(&[error]), [("file", "is not a PDF")]);
+
+        let error = validate(b"%PD", 1024).unwrap();
+        assert_eq!(pairs(&[error]), [("file", "is not a PDF")]);
+    }
+
+    #[test]
+    fn rejects_empty_and_oversize_files() {
+        let error = validate(b"", 1024).unwrap();
+        assert_eq!(pairs(&[error]), [("file", "is empty")]);
+
+        let bytes = pdf(1);
+        let error = validate(&bytes, bytes.len() - 1).unwrap();
+        let message = format!(
+            "is {} bytes, larger than the limit of {} bytes",
+            bytes.len(),
+            bytes.len() - 1
+        );
+        assert_eq!(pairs(&[error]), [("file", message.as_str())]);
+    }
+
+    #[test]
+    fn rejects_pdfs_without_pages() {
+        let error = validate(&pdf(0), 1024 * 1024).unwrap();
+        assert_eq!(pairs(&[error]), [("file", "is a PDF with no pages")]);
+    }
+
+    #[test]
+    fn rejects_pdfs_that_do_not_parse() {
+        let error = validate(b"%PDF-1.5\nnot really", 1024).unwrap();
+        assert_eq!(error.field, "file");
+        assert!(error.message.starts_with("could not be parsed as a PDF"));
+    }
+
+    #[test]
+    fn parses_valid_contributors() {
+        let contributors =
+            parse_contributors(&json!([{ "name": "Ann Example", "handle": "ann" }])).unwrap();
+        assert_eq!(contributors.len(), 1);
+        assert_eq!(contributors[0].name, "Ann Example");
+        assert_eq!(contributors[0].handle, "ann");
+        assert!(parse_contributors(&json!([])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_every_contributor_error_by_field() {
+        let errors = parse_contributors(&json!({ "name": "Ann" })).unwrap_err();
+        assert_eq!(pairs(&errors), [("contributors", "must be a JSON array")]);
+
+        let errors = parse_contributors(&json!([
+            { "name": "Ann Example", "handle": "ann" },
+            "bob",
+            { "handle": "" },
+            { "name": "  ", "handle": 7 },
+            { "name": null, "handle": "dee" },
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            pairs(&errors),
+            [
+                (
+                    "contributors[1]",
+                    "must be an object with `name` and `handle`"
+                ),
+                ("contributors[2].name", "is required"),
+                ("contributors[2].handle", "must not be empty"),
+                ("contributors[3].name", "must not be empty"),
+                ("contributors[3].handle", "must be a string"),
+                ("contributors[4].name", "is required"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replication_errors_name_their_subject() {
+        let status = |stored| ReplicaStatus {
+            region: "us-east-1".to_string(),
+            stored,
+            rolled_back: false,
+            error: None,
+        };
+        let report = |replicas| ReplicationReport {
+            key: "nnm_news/a.png".to_string(),
+            replicas,
+        };
+
+        let rolled_back = replication_error("Image", &report(vec![status(false)]));
+        assert_eq!(
+            rolled_back.to_string(),
+            "Image could not be stored in every region and was rolled back"
+        );
+        let inconsistent = replication_error("Issue", &report(vec![status(true), status(false)]));
+        assert!(inconsistent
+            .to_string()
+            .starts_with("Issue could not be stored in every region, and rolling back failed"));
+    }
+
+    fn upload_form(issue_number: usize, bytes: &[u8]) -> MultipartBody {
+        MultipartBody::new()
+            .file("file", "issue.pdf", "application/pdf", bytes)
+            .text("issue_number", &issue_number.to_string())
+            .text("blurb", "A new issue")
+            .text(
+                "contributors",
+                r#"[{ "name": "Ann Example", "handle": "ann" }]"#,
+            )
+    }
+
+    /// Sends `body` to `POST /upload` as an editor, returning the status and the JSON body.
+    async fn post(state: &AppState, body: MultipartBody) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state.clone()))
+                .service(upload),
+        )
+        .await;
+        let request = body.attach(
+            TestRequest::post()
+                .uri("/upload")
+                .insert_header(bearer(&["editor"])),
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    /// The keys stored under `prefix` in the state's blob store.
+    async fn keys(state: &AppState, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = state
+            .blob_store
+            .list(prefix)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|blob| blob.key)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Publishes `bytes` as issue `issue_number` directly in the store, with a metadata record.
+    async fn publish(state: &AppState, issue_number: usize, bytes: &[u8]) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), bytes).unwrap();
+        state
+            .blob_store
+            .put_file(&issue_key(issue_number), file.path())
+            .await
+            .unwrap();
+        let issue = db::DBIssue::new(issue_number, "The original".to_string(), Vec::new());
+        state.metadata.put_issue(issue).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn an_existing_issue_is_a_conflict_without_overwrite() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+        let original = pdf(1);
+        publish(&state, 1, &original).await;
+
+        let (status, body) = post(&state, upload_form(1, &pdf(3))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "conflict");
+
+        let (status, _) = post(&state, upload_form(1, &pdf(3)).text("overwrite", "false")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // Nothing was staged and the published issue is untouched
+        assert!(keys(&state, PENDING_PREFIX).await.is_empty());
+        assert_eq!(state.blob_store.get(&issue_key(1)).await.unwrap(), original);
+        assert_eq!(
+            state.metadata.get_issue(1).await.unwrap().blurb,
+            "The original"
+        );
+    }
+
+    #[actix_web::test]
+    async fn invalid_forms_are_rejected_before_anything_is_stored() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+
+        let body = MultipartBody::new()
+            .file("file", "issue.pdf", "application/pdf", b"not a pdf")
+            .text("issue_number", "0")
+            .text("blurb", "A new issue")
+            .text("contributors", r#"[{ "name": "Ann Example" }]"#);
+        let (status, body) = post(&state, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["details"]["errors"],
+            json!([
+                { "field": "issue_number", "message": "must be at least 1" },
+                { "field": "file", "message": "is not a PDF" },
+                { "field": "contributors[0].handle", "message": "is required" },
+            ])
+        );
+        assert!(keys(&state, "").await.is_empty());
+    }
+
+    /// Makes every copy into `nnm_issues/` fail, so uploads are staged but never promoted.
+    fn fail_promotion(state: &mut AppState) {
+        let faults = Faults {
+            copy: true,
+            ..Faults::default()
+        };
+        state.replicas = vec![Replica {
+            region: "local".to_string(),
+            store: Arc::new(FaultyStore::new(state.blob_store.clone(), faults)),
+        }];
+    }
+
+    #[actix_web::test]
+    async fn a_failed_metadata_write_deletes_the_staged_copies() {
+        let root = tempfile::tempdir().unwrap();
+        let mut state = app_state(root.path());
+        state.metadata = Arc::new(FaultyRepository::new(RepoFaults {
+            put_issue: true,
+            ..RepoFaults::default()
+        }));
+
+        let (status, body) = post(&state, upload_form(1, &pdf(1))).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "upstream_error");
+        assert!(keys(&state, PENDING_PREFIX).await.is_empty());
+        assert!(keys(&state, ISSUE_PREFIX).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn a_failed_promotion_restores_the_previous_issue() {
+        let root = tempfile::tempdir().unwrap();
+        let mut state = app_state(root.path());
+        let original = pdf(1);
+        publish(&state, 1, &original).await;
+        fail_promotion(&mut state);
+
+        let (status, body) = post(&state, upload_form(1, &pdf(3)).text("overwrite", "true")).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["code"], "replication_failed");
+        assert_eq!(
+            state.metadata.get_issue(1).await.unwrap().blurb,
+            "The original"
+        );
+        assert_eq!(state.blob_store.get(&issue_key(1)).await.unwrap(), original);
+        assert!(keys(&state, PENDING_PREFIX).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn a_failed_promotion_of_a_new_issue_removes_its_metadata() {
+        let root = tempfile::tempdir().unwrap();
+        let mut state = app_state(root.path());
+        fail_promotion(&mut state);
+
+        let (status, _) = post(&state, upload_form(2, &pdf(1))).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(matches!(
+            state.metadata.get_issue(2).await,
+            Err(MetadataError::NotFound(_))
+        ));
+        assert!(keys(&state, "").await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn a_successful_overwrite_leaves_nothing_staged() {
+        let root = tempfile::tempdir().unwrap();
+        let state = app_state(root.path());
+        publish(&state, 1, &pdf(1)).await;
+
+        let replacement = pdf(3);
+        let (status, body) = post(
+            &state,
+            upload_form(1, &replacement).text("overwrite", "true"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["key"], issue_key(1));
+        assert_eq!(body["replicas"][0]["stored"], true);
+        assert_eq!(
+            state.blob_store.get(&issue_key(1)).await.unwrap(),
+            replacement
+        );
+        assert_eq!(
+            state.metadata.get_issue(1).await.unwrap().blurb,
+            "A new issue"
+        );
+        assert_eq!(keys(&state, "").await, [issue_key(1)]);
+    }
+}
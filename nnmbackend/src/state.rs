@@ -0,0 +1,166 @@
+//! This module defines the state shared by every request handler.
+//!
+//! `AppState` is built once in `main` and handed to actix as `web::Data<AppState>`, so the
+//! AWS configuration chain is resolved a single time at startup and every handler reuses the
+//! same clients and settings. Handlers only see the `BlobStore` and `MetadataRepository`
+//! trait objects, which lets tests build an `AppState` around in-memory fakes.
+//!
+//! # Structs
+//!
+//! - `AppState`: Holds the configuration, the storage backends, the issue cache, the token verifier, the Shopify Storefront client and the product cache.
+//!
+//! Which backends are built is decided by the `Config` loaded in `main`. With S3, one store is
+//! built per configured regional bucket: reads go to the bucket for the server's own region,
+//! while uploads are written to all of them through `replicas`.
+
+use std::sync::Arc;
+
+use aws_config::{meta::region::RegionProviderChain, BehaviorVersion, SdkConfig};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::{config::Region, Client as S3Client};
+
+use crate::auth::TokenVerifier;
+use crate::config::{Config, MetadataBackend, StorageBackend};
+use crate::utils::{
+    dynamodb::{DynamoRepository, DynamoTables},
+    issues::cache::IssueCache,
+    metadata::{memory::InMemoryRepository, sqlite::SqliteRepository, MetadataRepository},
+    s3::{get_bucket_for_client, S3BlobStore},
+    shopify::{products::cache::ProductCache, ShopifyClient},
+    storage::{local::LocalBlobStore, replicated::Replica, BlobStore},
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub blob_store: Arc<dyn BlobStore>,
+    /// Every regional copy of the blob store, including `blob_store` itself.
+    pub replicas: Vec<Replica>,
+    /// Set when running against the local filesystem, so `/blob/{key}` can verify signatures.
+    pub local_blob_store: Option<Arc<LocalBlobStore>>,
+    pub metadata: Arc<dyn MetadataRepository>,
+    pub issue_cache: Arc<IssueCache>,
+    pub auth: Arc<TokenVerifier>,
+    pub shopify: ShopifyClient,
+    pub product_cache: Arc<ProductCache>,
+}
+
+async fn load_aws_config() -> SdkConfig {
+    let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
+    aws_config::defaults(BehaviorVersion::latest())
+        .region(region_provider)
+        .load()
+        .await
+}
+
+impl AppState {
+    pub async fn new(config: Config) -> Result<Self, std::io::Error> {
+        let aws_config = load_aws_config().await;
+
+        let (blob_store, replicas, local_blob_store): (Arc<dyn BlobStore>, _, _) =
+            match config.storage.backend {
+                StorageBackend::Local => {
+                    let local = &config.storage.local;
+                    // Without a configured secret, URLs only need to stay valid for this process
+                    let secret = local
+                        .signing_secret
+                        .clone()
+                        .map(String::into_bytes)
+                        .unwrap_or_else(|| rand::random::<[u8; 32]>().to_vec());
+                    let store = Arc::new(LocalBlobStore::new(
+                        local.root.clone(),
+                        local.public_url.clone(),
+                        secret,
+                    ));
+                    let replicas = vec![Replica {
+                        region: "local".to_string(),
+                        store: store.clone(),
+                    }];
+                    (store.clone(), replicas, Some(store))
+                }
+                StorageBackend::S3 => {
+                    let client = S3Client::new(&aws_config);
+                    let bucket = get_bucket_for_client(&client, &config.storage.buckets)
+                        .ok_or_else(|| {
+                            std::io::Error::other(format!(
+                                "No bucket configured in storage.buckets for region {:?}",
+                                aws_config.region().map(|r| r.as_ref().to_string())
+                            ))
+                        })?
+                        .to_string();
+                    let replicas = config
+                        .storage
+                        .buckets
+                        .iter()
+                        .map(|(region, bucket)| {
+                            let s3_config = aws_sdk_s3::config::Builder::from(&aws_config)
+                                .region(Region::new(region.clone()))
+                                .build();
+                            Replica {
+                                region: region.clone(),
+                                store: Arc::new(S3BlobStore::new(
+                                    S3Client::from_conf(s3_config),
+                                    bucket.clone(),
+                                    config.storage.multipart_part_size(),
+                                )),
+                            }
+                        })
+                        .collect();
+                    let store =
+                        S3BlobStore::new(client, bucket, config.storage.multipart_part_size());
+                    (Arc::new(store), replicas, None)
+                }
+            };
+
+        let metadata: Arc<dyn MetadataRepository> = match config.metadata.backend {
+            MetadataBackend::Memory => Arc::new(InMemoryRepository::new()),
+            MetadataBackend::Sqlite => Arc::new(
+                SqliteRepository::open(&config.metadata.sqlite_path)
+                    .map_err(std::io::Error::other)?,
+            ),
+            MetadataBackend::DynamoDB => {
+                let repo = DynamoRepository::new(
+                    DynamoClient::new(&aws_config),
+                    DynamoTables {
+                        issues: config.metadata.issue_table.clone(),
+                        news: config.metadata.news_table.clone(),
+                        news_index: config.metadata.news_index.clone(),
+                    },
+                );
+                // News items from before the feed index are invisible to GET /news until
+                // backfilled. A failure here only hides those items, so it is not fatal.
+                match repo.backfill_news_feed().await {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("Backfilled the news feed index keys of {} items", n),
+                    Err(e) => log::warn!("Could not backfill legacy news items: {}", e),
+                }
+                Arc::new(repo)
+            }
+        };
+
+        let issue_cache = Arc::new(IssueCache::new(
+            blob_store.clone(),
+            metadata.clone(),
+            config.cache.issue_ttl(),
+        ));
+
+        let auth = Arc::new(TokenVerifier::new(&config.auth).map_err(std::io::Error::other)?);
+        let shopify = ShopifyClient::new(&config.shopify).map_err(std::io::Error::other)?;
+        let product_cache = Arc::new(ProductCache::new(
+            shopify.clone(),
+            config.cache.product_ttl(),
+        ));
+
+        Ok(AppState {
+            config: Arc::new(config),
+            blob_store,
+            replicas,
+            local_blob_store,
+            metadata,
+            issue_cache,
+            auth,
+            shopify,
+            product_cache,
+        })
+    }
+}
@@ -0,0 +1,334 @@
+//! Helpers shared by the route tests.
+//!
+//! Route tests run against an `AppState` built from the local filesystem store and the
+//! in-memory metadata repository, with static token verification, so they need no network
+//! access or AWS credentials.
+//!
+//! # Functions
+//!
+//! - `app_state`: Builds an `AppState` whose blob store lives in the given directory.
+//! - `auth_config`: The static auth settings every test token is signed for.
+//! - `claims`, `sign` and `token`: Build and sign access tokens for users in given Cognito groups.
+//! - `bearer`: The `Authorization` header carrying such a token.
+//!
+//! - `local_store`: A `LocalBlobStore` in the given directory, signing with `SECRET`.
+//!
+//! # Structs
+//!
+//! - `MultipartBody`: Builds a `multipart/form-data` request body.
+//! - `FaultyStore`: A `BlobStore` that fails the operations chosen in `Faults`.
+//! - `FaultyRepository`: An in-memory `MetadataRepository` that fails the writes chosen in `RepoFaults`.
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use actix_web::{http::header, test::TestRequest};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use jsonwebtoken::{EncodingKey, Header};
+use serde_json::{json, Value};
+
+use crate::{
+    auth::TokenVerifier,
+    config::{AuthConfig, AuthMode, Config},
+    state::AppState,
+    utils::{
+        dynamodb::DBIssue,
+        issues::cache::IssueCache,
+        metadata::{
+            memory::InMemoryRepository, IssueUpdate, MetadataError, MetadataRepository,
+            NewNewsItem, NewsCursor, NewsFilter, NewsPage, NewsUpdate,
+        },
+        news::NewsItem,
+        shopify::{products::cache::ProductCache, ShopifyClient},
+        storage::{local::LocalBlobStore, replicated::Replica, BlobInfo, BlobStore, StorageError},
+    },
+};
+
+pub const SECRET: &str = "test-secret";
+pub const ISSUER: &str = "nnm-test";
+pub const CLIENT_ID: &str = "test-client";
+
+/// Static token verification with `SECRET`, `ISSUER` and `CLIENT_ID`.
+pub fn auth_config() -> AuthConfig {
+    AuthConfig {
+        mode: AuthMode::Static,
+        client_id: CLIENT_ID.to_string(),
+        static_secret: Some(SECRET.to_string()),
+        static_issuer: ISSUER.to_string(),
+        ..AuthConfig::default()
+    }
+}
+
+/// A `LocalBlobStore` keeping its objects under `root`.
+pub fn local_store(root: &Path) -> Arc<LocalBlobStore> {
+    Arc::new(LocalBlobStore::new(
+        root.to_path_buf(),
+        "http://localhost".to_string(),
+        SECRET.as_bytes().to_vec(),
+    ))
+}
+
+/// An `AppState` storing blobs under `root`, with a single `local` replica, in-memory
+/// metadata and static auth.
+pub fn app_state(root: &Path) -> AppState {
+    let config = Config {
+        auth: auth_config(),
+        ..Config::default()
+    };
+    let store = local_store(root);
+    let metadata = Arc::new(InMemoryRepository::new());
+    let shopify = ShopifyClient::new(&config.shopify).unwrap();
+    AppState {
+        issue_cache: Arc::new(IssueCache::new(
+            store.clone(),
+            metadata.clone(),
+            Duration::from_secs(60),
+        )),
+        product_cache: Arc::new(ProductCache::new(shopify.clone(), Duration::from_secs(60))),
+        auth: Arc::new(TokenVerifier::new(&config.auth).unwrap()),
+        config: Arc::new(config),
+        blob_store: store.clone(),
+        replicas: vec![Replica {
+            region: "local".to_string(),
+            store: store.clone(),
+        }],
+        local_blob_store: Some(store),
+        metadata,
+        shopify,
+    }
+}
+
+/// Claims for a valid access token for a user in the Cognito groups `groups`.
+pub fn claims(groups: &[&str]) -> Value {
+    json!({
+        "sub": "user-1",
+        "username": "ann",
+        "iss": ISSUER,
+        "exp": chrono::Utc::now().timestamp() + 600,
+        "token_use": "access",
+        "client_id": CLIENT_ID,
+        "cognito:groups": groups,
+    })
+}
+
+pub fn sign(claims: &Value, secret: &str) -> String {
+    jsonwebtoken::encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// A valid access token for a user in the Cognito groups `groups`.
+pub fn token(groups: &[&str]) -> String {
+    sign(&claims(groups), SECRET)
+}
+
+/// The `Authorization` header for a user in the Cognito groups `groups`.
+pub fn bearer(groups: &[&str]) -> (header::HeaderName, String) {
+    (header::AUTHORIZATION, format!("Bearer {}", token(groups)))
+}
+
+const BOUNDARY: &str = "nnm-test-boundary";
+
+/// A `multipart/form-data` body, built up one field at a time.
+#[derive(Debug, Default)]
+pub struct MultipartBody {
+    body: Vec<u8>,
+}
+
+impl MultipartBody {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+        self
+    }
+
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, bytes: &[u8]) -> Self {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                 Content-Type: {}\r\n\r\n",
+                BOUNDARY, name, filename, content_type
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(bytes);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Sets the body and its content type on `request`.
+    pub fn attach(mut self, request: TestRequest) -> TestRequest {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        request
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            ))
+            .set_payload(self.body)
+    }
+}
+
+/// The `BlobStore` operations a `FaultyStore` fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    pub put: bool,
+    pub copy: bool,
+    pub delete: bool,
+    pub sign: bool,
+}
+
+/// A `BlobStore` that forwards to `inner`, except for the operations chosen in `faults`, which
+/// fail with a backend error.
+pub struct FaultyStore {
+    pub inner: Arc<dyn BlobStore>,
+    pub faults: Faults,
+}
+
+impl FaultyStore {
+    pub fn new(inner: Arc<dyn BlobStore>, faults: Faults) -> Self {
+        FaultyStore { inner, faults }
+    }
+
+    fn check(&self, failing: bool, operation: &str, key: &str) -> Result<(), StorageError> {
+        if failing {
+            return Err(StorageError::Backend(anyhow!(
+                "injected {} failure for {}",
+                operation,
+                key
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobStore for FaultyStore {
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>, StorageError> {
+        self.inner.list(prefix).await
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), StorageError> {
+        self.check(self.faults.put, "put", key)?;
+        self.inner.put_file(key, path).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        self.check(self.faults.copy, "copy", to)?;
+        self.inner.copy(from, to).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.check(self.faults.delete, "delete", key)?;
+        self.inner.delete(key).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.inner.get(key).await
+    }
+
+    async fn signed_url(&self, key: &str, ttl: Duration) -> Result<String, StorageError> {
+        self.check(self.faults.sign, "signing", key)?;
+        self.inner.signed_url(key, ttl).await
+    }
+}
+
+/// The `MetadataRepository` writes a `FaultyRepository` fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepoFaults {
+    pub put_issue: bool,
+    pub update_news: bool,
+}
+
+/// A `MetadataRepository` that forwards to `inner`, except for the writes chosen in `faults`,
+/// which fail with a backend error.
+#[derive(Debug, Default)]
+pub struct FaultyRepository {
+    pub inner: InMemoryRepository,
+    pub faults: RepoFaults,
+}
+
+impl FaultyRepository {
+    pub fn new(faults: RepoFaults) -> Self {
+        FaultyRepository {
+            inner: InMemoryRepository::new(),
+            faults,
+        }
+    }
+
+    fn check(&self, failing: bool, operation: &str) -> Result<(), MetadataError> {
+        if failing {
+            return Err(MetadataError::Backend(anyhow!(
+                "injected {} failure",
+                operation
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MetadataRepository for FaultyRepository {
+    async fn get_issue(&self, issue_number: usize) -> Result<DBIssue, MetadataError> {
+        self.inner.get_issue(issue_number).await
+    }
+
+    async fn list_issues(&self) -> Result<Vec<DBIssue>, MetadataError> {
+        self.inner.list_issues().await
+    }
+
+    async fn put_issue(&self, issue: DBIssue) -> Result<(), MetadataError> {
+        self.check(self.faults.put_issue, "put_issue")?;
+        self.inner.put_issue(issue).await
+    }
+
+    async fn update_issue(
+        &self,
+        issue_number: usize,
+        update: IssueUpdate,
+    ) -> Result<DBIssue, MetadataError> {
+        self.inner.update_issue(issue_number, update).await
+    }
+
+    async fn delete_issue(&self, issue_number: usize) -> Result<(), MetadataError> {
+        self.inner.delete_issue(issue_number).await
+    }
+
+    async fn list_news(
+        &self,
+        limit: usize,
+        after: Option<NewsCursor>,
+        filter: NewsFilter,
+    ) -> Result<NewsPage, MetadataError> {
+        self.inner.list_news(limit, after, filter).await
+    }
+
+    async fn get_news(&self, id: &str) -> Result<NewsItem, MetadataError> {
+        self.inner.get_news(id).await
+    }
+
+    async fn create_news(&self, item: NewNewsItem) -> Result<NewsItem, MetadataError> {
+        self.inner.create_news(item).await
+    }
+
+    async fn update_news(&self, id: &str, update: NewsUpdate) -> Result<NewsItem, MetadataError> {
+        self.check(self.faults.update_news, "update_news")?;
+        self.inner.update_news(id, update).await
+    }
+
+    async fn delete_news(&self, id: &str) -> Result<(), MetadataError> {
+        self.inner.delete_news(id).await
+    }
+}
@@ -1,55 +1,110 @@
-/// This module provides utility functions for interacting with DynamoDB.
-///
-/// The functions include creating a DynamoDB client, retrieving issue data, and storing issue data.
-///
-/// # Structs
-///
-/// - `DBContributor`: Represents a contributor with a name and handle.
-/// - `DBIssue`: Represents an issue with a number, blurb, and a list of contributors.
-///
-/// # Functions
-///
-/// - `get_db_client`: Asynchronously creates and returns a DynamoDB client.
-/// - `get_issue_data`: Asynchronously retrieves issue data from DynamoDB based on the issue number.
-/// - `put_issue_data`: Asynchronously stores issue data in DynamoDB.
-///
-/// # Example
-///
-/// ```
-/// use nnmbackend::utils::dynamodb::{get_db_client, get_issue_data, put_issue_data, DBIssue, DBContributor};
-/// use aws_sdk_dynamodb::Client;
-///
-/// #[tokio::main]
-/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     let client = get_db_client().await?;
-///
-///     let issue = DBIssue::new(
-///         1,
-///         "Issue blurb".to_string(),
-///         vec![DBContributor {
-///             name: "Contributor Name".to_string(),
-///             handle: "contributor_handle".to_string(),
-///         }],
-///     );
-///
-///     put_issue_data(issue, &client).await?;
-///
-///     let retrieved_issue = get_issue_data(1, &client).await?;
-///     println!("{:?}", retrieved_issue);
-///
-///     Ok(())
-/// }
-/// ```
-///
-/// # Errors
-///
-/// - `get_db_client`: Returns an `Error` if there is an issue creating the DynamoDB client.
-/// - `get_issue_data`: Returns an `Error` if there is an issue retrieving the item from DynamoDB or parsing the item attributes.
-/// - `put_issue_data`: Returns an `Error` if there is an issue storing the item in DynamoDB.
+//! This module provides utility functions for interacting with DynamoDB.
+//!
+//! The functions include creating a DynamoDB client, retrieving issue data, storing issue data
+//! and reading news items. `DynamoRepository` adapts them to the `MetadataRepository` trait
+//! used by the routes.
+//!
+//! # Structs
+//!
+//! - `DBContributor`: Represents a contributor with a name and handle.
+//! - `DBIssue`: Represents an issue with a number, blurb, and a list of contributors.
+//! - `DynamoTables`: The names of the tables holding issue and news data.
+//! - `DynamoRepository`: The DynamoDB-backed `MetadataRepository`.
+//!
+//! # Functions
+//!
+//! - `get_issue_data`: Asynchronously retrieves issue data from DynamoDB based on the issue number.
+//! - `put_issue_data`: Asynchronously stores issue data in DynamoDB.
+//! - `list_issue_data`: Asynchronously retrieves every issue record from DynamoDB.
+//! - `update_issue_data`: Asynchronously changes the blurb and/or contributors of an existing issue.
+//! - `delete_issue_data`: Asynchronously removes an issue record from DynamoDB.
+//! - `get_news_items`: Asynchronously retrieves one page of news items matching a `NewsFilter`, newest first.
+//! - `get_news_item`, `create_news_item`, `update_news_item`, `delete_news_item`: Read and manage a single news item by its `id` key.
+//! - `backfill_news_feed`: Asynchronously adds the news feed index keys to items written before they existed.
+//!
+//! # Contributors
+//!
+//! An issue's `contributors` attribute is a list of maps with the string attributes `name` and
+//! `handle`. Older items hold each contributor as a string set instead, which DynamoDB neither
+//! orders nor keeps duplicates in; these are still read, taking the first string as the handle
+//! and the second as the name as before, and are rewritten as maps the next time the issue's
+//! contributors are saved.
+//!
+//! # News feed index
+//!
+//! The news feed is read with a query on a global secondary index of the news table, named by
+//! `metadata.news_index`, rather than a scan, so items come back in publication order. The
+//! index has the string partition key `feed`, which every item sets to `"news"`, and the string
+//! sort key `published_at`, an RFC 3339 timestamp with millisecond precision. It must project
+//! all attributes. Items written before `published_at` existed have neither attribute and so
+//! are not in the index; `backfill_news_feed`, run when the repository is opened, gives them
+//! `feed` and a `published_at` of the Unix epoch, placing them after every dated item. Items
+//! without a `status` are treated as published. `publish_at`, set on scheduled items, is an ordinary attribute; the
+//! feed only needs `published_at`, which holds the same time.
+//!
+//! Drafts and scheduled items share the index with live ones and are dropped by a filter
+//! expression. DynamoDB applies `Limit` before filtering, so `get_news_items` keeps querying
+//! until it has a full page or reaches the end of the index.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::dynamodb::{get_issue_data, put_issue_data, DBIssue, DBContributor};
+//! use aws_sdk_dynamodb::Client;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let config = aws_config::load_from_env().await;
+//!     let client = Client::new(&config);
+//!
+//!     let issue = DBIssue::new(
+//!         1,
+//!         "Issue blurb".to_string(),
+//!         vec![DBContributor {
+//!             name: "Contributor Name".to_string(),
+//!             handle: "contributor_handle".to_string(),
+//!         }],
+//!     );
+//!
+//!     put_issue_data(issue, "nnmIssueData", &client).await?;
+//!
+//!     let retrieved_issue = get_issue_data(1, "nnmIssueData", &client).await?;
+//!     println!("{:?}", retrieved_issue);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! - `get_issue_data`: Returns `MetadataError::NotFound` if there is no such issue, `MetadataError::Malformed` if the item attributes cannot be parsed, and `MetadataError::Backend` if DynamoDB fails.
+//! - `put_issue_data`: Returns an `Error` if there is an issue storing the item in DynamoDB.
+//! - `update_issue_data`: Returns `MetadataError::NotFound` if there is no such issue, and `MetadataError::Backend` if DynamoDB fails.
+//! - `delete_issue_data`: Returns `MetadataError::NotFound` if there is no such issue, and `MetadataError::Backend` if DynamoDB fails.
+//! - `get_news_items`: Returns `MetadataError::Malformed` if a news item is missing an attribute, and `MetadataError::Backend` if DynamoDB fails.
+//! - The single news item functions return `MetadataError::NotFound` if there is no item with that id.
+//! - `backfill_news_feed`: Returns `MetadataError::Malformed` if an item has no `id`, and `MetadataError::Backend` if DynamoDB fails.
+
+use std::collections::HashMap;
 
-use anyhow::{anyhow, Error};
-use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
-use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    operation::{
+        delete_item::DeleteItemError, put_item::PutItemError, update_item::UpdateItemError,
+    },
+    types::{AttributeValue, ReturnValue},
+    Client as DynamoClient,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+
+use super::{
+    metadata::{
+        IssueUpdate, MetadataError, MetadataRepository, NewNewsItem, NewsCursor, NewsFilter,
+        NewsPage, NewsUpdate,
+    },
+    news::{format_published_at, NewsItem, NewsStatus},
+};
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct DBContributor {
@@ -74,44 +129,89 @@ impl DBIssue {
     }
 }
 
-pub async fn get_db_client() -> Result<DynamoClient, Error> {
-    let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .region(region_provider)
-        .load()
-        .await;
-    Ok(DynamoClient::new(&config))
+#[derive(Debug, Clone)]
+pub struct DynamoTables {
+    pub issues: String,
+    pub news: String,
+    /// The global secondary index of `news` that orders items by publication time.
+    pub news_index: String,
+}
+
+/// The `feed` partition key value shared by every news item in the news feed index.
+const NEWS_FEED: &str = "news";
+
+/// Reads a string attribute, reporting which attribute was missing or of the wrong type.
+fn string_attribute(
+    item: &HashMap<String, AttributeValue>,
+    name: &str,
+) -> Result<String, MetadataError> {
+    item.get(name)
+        .ok_or(MetadataError::Malformed(format!("{} not found", name)))?
+        .as_s()
+        .map(|s| s.to_string())
+        .map_err(|_| MetadataError::Malformed(format!("{} is not a string", name)))
+}
+
+fn parse_contributor(contributor: &AttributeValue) -> Result<DBContributor, MetadataError> {
+    if let Ok(fields) = contributor.as_m() {
+        return Ok(DBContributor {
+            name: string_attribute(fields, "name")?,
+            handle: string_attribute(fields, "handle")?,
+        });
+    }
+    // Legacy string sets. A contributor whose name and handle were equal was stored as a
+    // single string.
+    match contributor.as_ss().map(Vec::as_slice) {
+        Ok([handle, name]) => Ok(DBContributor {
+            name: name.to_string(),
+            handle: handle.to_string(),
+        }),
+        Ok([both]) => Ok(DBContributor {
+            name: both.to_string(),
+            handle: both.to_string(),
+        }),
+        _ => Err(MetadataError::Malformed(format!(
+            "contributor {:?} is neither a map nor a set of two strings",
+            contributor
+        ))),
+    }
 }
 
-pub async fn get_issue_data(issue_number: usize, client: &DynamoClient) -> Result<DBIssue, Error> {
+pub async fn get_issue_data(
+    issue_number: usize,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<DBIssue, MetadataError> {
     let response = client
         .get_item()
-        .table_name("nnmIssueData")
+        .table_name(table_name)
         .key("issueNumber", AttributeValue::N(issue_number.to_string()))
         .send()
-        .await?;
+        .await
+        .map_err(|e| anyhow!(aws_sdk_dynamodb::Error::from(e)))?;
 
-    let item = response.item.ok_or(anyhow!("Could not retrieve item"))?;
-    let blurb = item
-        .get("blurb")
-        .ok_or(anyhow!("Blurb not found"))?
-        .as_s()
-        .map_err(|e| anyhow!(format!("{:?}", e)))?
-        .to_string();
+    let Some(item) = response.item else {
+        return Err(MetadataError::NotFound(format!("issue {}", issue_number)));
+    };
+    parse_issue(issue_number, &item)
+}
+
+fn parse_issue(
+    issue_number: usize,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<DBIssue, MetadataError> {
+    let blurb = string_attribute(item, "blurb")?;
 
     let contributors = item
         .get("contributors")
-        .ok_or(anyhow!("Contributors not found"))?
+        .ok_or(MetadataError::Malformed(
+            "contributors not found".to_string(),
+        ))?
         .as_l()
-        .map_err(|e| anyhow!(format!("{:?}", e)))?
+        .map_err(|_| MetadataError::Malformed("contributors is not a list".to_string()))?
         .iter()
-        .map(|contributor| {
-            let contributor = contributor.as_ss().unwrap(); // String-sets
-            let handle = contributor.first().unwrap().to_string();
-            let name = contributor.get(1).unwrap().to_string();
-            DBContributor { name, handle }
-        })
-        .collect();
+        .map(parse_contributor)
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(DBIssue {
         number: issue_number,
@@ -120,29 +220,616 @@ pub async fn get_issue_data(issue_number: usize, client: &DynamoClient) -> Resul
     })
 }
 
-pub async fn put_issue_data(
-    issue: DBIssue,
+pub async fn list_issue_data(
+    table_name: &str,
     client: &DynamoClient,
-) -> Result<(), aws_sdk_dynamodb::Error> {
-    let contributors = AttributeValue::L(
-        issue
-            .contributors
+) -> Result<Vec<DBIssue>, MetadataError> {
+    let mut pages = client.scan().table_name(table_name).into_paginator().send();
+
+    let mut issues = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| anyhow!(aws_sdk_dynamodb::Error::from(e)))?;
+        for item in page.items() {
+            let issue_number = item
+                .get("issueNumber")
+                .and_then(|n| n.as_n().ok())
+                .and_then(|n| n.parse::<usize>().ok())
+                .ok_or(MetadataError::Malformed(
+                    "issueNumber is missing or not a number".to_string(),
+                ))?;
+            issues.push(parse_issue(issue_number, item)?);
+        }
+    }
+    issues.sort_by_key(|issue| issue.number);
+    Ok(issues)
+}
+
+fn contributors_attribute(contributors: &[DBContributor]) -> AttributeValue {
+    AttributeValue::L(
+        contributors
             .iter()
             .map(|contributor| {
-                AttributeValue::Ss(vec![
-                    contributor.name.to_string(),
-                    contributor.handle.to_string(),
-                ])
+                AttributeValue::M(HashMap::from([
+                    (
+                        "name".to_string(),
+                        AttributeValue::S(contributor.name.to_string()),
+                    ),
+                    (
+                        "handle".to_string(),
+                        AttributeValue::S(contributor.handle.to_string()),
+                    ),
+                ]))
             })
-            .collect::<Vec<AttributeValue>>(),
-    );
+            .collect(),
+    )
+}
+
+pub async fn put_issue_data(
+    issue: DBIssue,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<(), aws_sdk_dynamodb::Error> {
     client
         .put_item()
-        .table_name("nnmIssueData")
+        .table_name(table_name)
         .item("issueNumber", AttributeValue::N(issue.number.to_string()))
         .item("blurb", AttributeValue::S(issue.blurb))
-        .item("contributors", contributors)
+        .item("contributors", contributors_attribute(&issue.contributors))
         .send()
         .await?;
     Ok(())
 }
+
+pub async fn update_issue_data(
+    issue_number: usize,
+    update: IssueUpdate,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<DBIssue, MetadataError> {
+    let mut assignments = Vec::new();
+    let mut request = client
+        .update_item()
+        .table_name(table_name)
+        .key("issueNumber", AttributeValue::N(issue_number.to_string()))
+        .condition_expression("attribute_exists(issueNumber)")
+        .return_values(ReturnValue::AllNew);
+    if let Some(blurb) = update.blurb {
+        assignments.push("blurb = :blurb");
+        request = request.expression_attribute_values(":blurb", AttributeValue::S(blurb));
+    }
+    if let Some(contributors) = update.contributors {
+        assignments.push("contributors = :contributors");
+        request = request
+            .expression_attribute_values(":contributors", contributors_attribute(&contributors));
+    }
+    if assignments.is_empty() {
+        // DynamoDB rejects an empty update expression
+        return get_issue_data(issue_number, table_name, client).await;
+    }
+
+    let response = request
+        .update_expression(format!("SET {}", assignments.join(", ")))
+        .send()
+        .await
+        .map_err(|e| match e.into_service_error() {
+            UpdateItemError::ConditionalCheckFailedException(_) => {
+                MetadataError::NotFound(format!("issue {}", issue_number))
+            }
+            other => MetadataError::Backend(anyhow!(aws_sdk_dynamodb::Error::from(other))),
+        })?;
+    let item = response.attributes.ok_or(MetadataError::Malformed(format!(
+        "update of issue {} returned no attributes",
+        issue_number
+    )))?;
+    parse_issue(issue_number, &item)
+}
+
+pub async fn delete_issue_data(
+    issue_number: usize,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<(), MetadataError> {
+    client
+        .delete_item()
+        .table_name(table_name)
+        .key("issueNumber", AttributeValue::N(issue_number.to_string()))
+        // Without the condition DynamoDB reports success for missing items
+        .condition_expression("attribute_exists(issueNumber)")
+        .send()
+        .await
+        .map_err(|e| match e.into_service_error() {
+            DeleteItemError::ConditionalCheckFailedException(_) => {
+                MetadataError::NotFound(format!("issue {}", issue_number))
+            }
+            other => MetadataError::Backend(anyhow!(aws_sdk_dynamodb::Error::from(other))),
+        })?;
+    Ok(())
+}
+
+/// Adds `feed` and `published_at` to news items that lack them, so they appear in the news feed
+/// index. Items that already have a `published_at` keep it. Returns the number of items updated;
+/// once every item has been backfilled this is a scan that updates nothing.
+pub async fn backfill_news_feed(
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<usize, MetadataError> {
+    let mut pages = client
+        .scan()
+        .table_name(table_name)
+        .filter_expression("attribute_not_exists(feed) OR attribute_not_exists(published_at)")
+        .projection_expression("id")
+        .into_paginator()
+        .send();
+
+    let mut ids = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| anyhow!(aws_sdk_dynamodb::Error::from(e)))?;
+        for item in page.items() {
+            ids.push(string_attribute(item, "id")?);
+        }
+    }
+
+    let epoch = format_published_at(DateTime::<Utc>::UNIX_EPOCH);
+    let mut updated = 0;
+    for id in &ids {
+        let result = client
+            .update_item()
+            .table_name(table_name)
+            .key("id", AttributeValue::S(id.clone()))
+            .update_expression(
+                "SET feed = :feed, published_at = if_not_exists(published_at, :epoch)",
+            )
+            // Skips items deleted since the scan rather than recreating them
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":feed", AttributeValue::S(NEWS_FEED.to_string()))
+            .expression_attribute_values(":epoch", AttributeValue::S(epoch.clone()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => updated += 1,
+            Err(e) => match e.into_service_error() {
+                UpdateItemError::ConditionalCheckFailedException(_) => {}
+                other => return Err(anyhow!(aws_sdk_dynamodb::Error::from(other)).into()),
+            },
+        }
+    }
+    Ok(updated)
+}
+
+/// The news feed index key of the item `cursor` names, used to resume a query after it.
+fn news_start_key(cursor: &NewsCursor) -> HashMap<String, AttributeValue> {
+    // The start key of a GSI query holds the index keys as well as the table key
+    HashMap::from([
+        ("feed".to_string(), AttributeValue::S(NEWS_FEED.to_string())),
+        (
+            "published_at".to_string(),
+            AttributeValue::S(format_published_at(cursor.published_at)),
+        ),
+        ("id".to_string(), AttributeValue::S(cursor.id.clone())),
+    ])
+}
+
+pub async fn get_news_items(
+    limit: usize,
+    after: Option<&NewsCursor>,
+    filter: NewsFilter,
+    table_name: &str,
+    index_name: &str,
+    client: &DynamoClient,
+) -> Result<NewsPage, MetadataError> {
+    let mut items = Vec::new();
+    let mut start_key = after.map(news_start_key);
+    loop {
+        let mut request = client
+            .query()
+            .table_name(table_name)
+            .index_name(index_name)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":feed", AttributeValue::S(NEWS_FEED.to_string()))
+            .projection_expression(
+                "id, title, description, image_name, #status, published_at, publish_at",
+            )
+            .scan_index_forward(false)
+            .limit(limit as i32)
+            .set_exclusive_start_key(start_key.take());
+        request = match filter {
+            NewsFilter::LiveAt(now) => request
+                .key_condition_expression("feed = :feed AND published_at <= :now")
+                .filter_expression("attribute_not_exists(#status) OR #status = :published")
+                .expression_attribute_values(":now", AttributeValue::S(format_published_at(now)))
+                .expression_attribute_values(
+                    ":published",
+                    AttributeValue::S(NewsStatus::Published.as_str().to_string()),
+                ),
+            NewsFilter::All => request.key_condition_expression("feed = :feed"),
+        };
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!(aws_sdk_dynamodb::Error::from(e)))?;
+        for item in response.items() {
+            items.push(parse_news(item)?);
+        }
+
+        // DynamoDB can report more results when the page ended exactly at the last item, in
+        // which case the next page is empty
+        let more = response.last_evaluated_key().is_some();
+        if items.len() >= limit || !more {
+            let has_more = items.len() > limit || more;
+            items.truncate(limit);
+            let next_cursor = if has_more {
+                items.last().map(NewsCursor::after)
+            } else {
+                None
+            };
+            return Ok(NewsPage { items, next_cursor });
+        }
+        start_key = response.last_evaluated_key;
+    }
+}
+
+fn parse_news(item: &HashMap<String, AttributeValue>) -> Result<NewsItem, MetadataError> {
+    Ok(NewsItem {
+        id: string_attribute(item, "id")?,
+        title: string_attribute(item, "title")?,
+        description: string_attribute(item, "description")?,
+        image_name: string_attribute(item, "image_name")?,
+        status: match item.get("status") {
+            None => NewsStatus::Published,
+            Some(_) => string_attribute(item, "status")?
+                .parse()
+                .map_err(MetadataError::Malformed)?,
+        },
+        published_at: string_attribute(item, "published_at")?
+            .parse()
+            .map_err(|e| MetadataError::Malformed(format!("published_at is invalid: {}", e)))?,
+        publish_at: match item.get("publish_at") {
+            None => None,
+            Some(_) => {
+                Some(string_attribute(item, "publish_at")?.parse().map_err(|e| {
+                    MetadataError::Malformed(format!("publish_at is invalid: {}", e))
+                })?)
+            }
+        },
+        image_url: None,
+    })
+}
+
+pub async fn get_news_item(
+    id: &str,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<NewsItem, MetadataError> {
+    let response = client
+        .get_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(id.to_string()))
+        .send()
+        .await
+        .map_err(|e| anyhow!(aws_sdk_dynamodb::Error::from(e)))?;
+    let item = response
+        .item
+        .ok_or(MetadataError::NotFound(format!("news item {}", id)))?;
+    parse_news(&item)
+}
+
+pub async fn create_news_item(
+    item: NewNewsItem,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<NewsItem, MetadataError> {
+    let item = NewsItem {
+        id: hex::encode(rand::random::<[u8; 16]>()),
+        title: item.title,
+        description: item.description,
+        image_name: item.image_name,
+        status: item.status,
+        published_at: item.published_at,
+        publish_at: item.publish_at,
+        image_url: None,
+    };
+    let mut request = client
+        .put_item()
+        .table_name(table_name)
+        .item("id", AttributeValue::S(item.id.clone()))
+        .item("title", AttributeValue::S(item.title.clone()))
+        .item("description", AttributeValue::S(item.description.clone()))
+        .item("image_name", AttributeValue::S(item.image_name.clone()))
+        .item(
+            "status",
+            AttributeValue::S(item.status.as_str().to_string()),
+        )
+        .item("feed", AttributeValue::S(NEWS_FEED.to_string()))
+        .item(
+            "published_at",
+            AttributeValue::S(format_published_at(item.published_at)),
+        );
+    if let Some(publish_at) = item.publish_at {
+        request = request.item(
+            "publish_at",
+            AttributeValue::S(format_published_at(publish_at)),
+        );
+    }
+    request
+        // Guards against the (vanishingly unlikely) reuse of a random id
+        .condition_expression("attribute_not_exists(id)")
+        .send()
+        .await
+        .map_err(|e| match e.into_service_error() {
+            PutItemError::ConditionalCheckFailedException(_) => {
+                MetadataError::Backend(anyhow!("news item id {} already exists", item.id))
+            }
+            other => MetadataError::Backend(anyhow!(aws_sdk_dynamodb::Error::from(other))),
+        })?;
+    Ok(item)
+}
+
+/// Sends one `update_item` for `update`. With `published_now`, the item is also moved to that
+/// time in the feed and its schedule removed, but only if it is still a draft.
+///
+/// Returns `Ok(None)` if the condition failed: the item does not exist or, with
+/// `published_now`, is not a draft.
+async fn send_news_update(
+    id: &str,
+    update: &NewsUpdate,
+    published_now: Option<DateTime<Utc>>,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<Option<NewsItem>, MetadataError> {
+    let mut assignments = Vec::new();
+    let mut request = client
+        .update_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(id.to_string()))
+        .return_values(ReturnValue::AllNew);
+    let fields = [
+        ("title", update.title.clone()),
+        ("description", update.description.clone()),
+        ("image_name", update.image_name.clone()),
+        (
+            "status",
+            update.status.map(|status| status.as_str().to_string()),
+        ),
+        // Rescheduling moves the item in the feed as well
+        ("publish_at", update.publish_at.map(format_published_at)),
+        (
+            "published_at",
+            update.publish_at.or(published_now).map(format_published_at),
+        ),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            // Attribute names are aliased in case they collide with DynamoDB reserved words
+            assignments.push(format!("#{name} = :{name}"));
+            request = request
+                .expression_attribute_names(format!("#{name}"), name)
+                .expression_attribute_values(format!(":{name}"), AttributeValue::S(value));
+        }
+    }
+    let mut expression = format!("SET {}", assignments.join(", "));
+    request = match published_now {
+        Some(_) => {
+            expression.push_str(" REMOVE publish_at");
+            request
+                .condition_expression("attribute_exists(id) AND #status = :draft")
+                .expression_attribute_values(
+                    ":draft",
+                    AttributeValue::S(NewsStatus::Draft.as_str().to_string()),
+                )
+        }
+        None => request.condition_expression("attribute_exists(id)"),
+    };
+
+    let response = match request.update_expression(expression).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return match e.into_service_error() {
+                UpdateItemError::ConditionalCheckFailedException(_) => Ok(None),
+                other => Err(MetadataError::Backend(anyhow!(
+                    aws_sdk_dynamodb::Error::from(other)
+                ))),
+            }
+        }
+    };
+    let item = response.attributes.ok_or(MetadataError::Malformed(format!(
+        "update of news item {} returned no attributes",
+        id
+    )))?;
+    parse_news(&item).map(Some)
+}
+
+pub async fn update_news_item(
+    id: &str,
+    update: NewsUpdate,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<NewsItem, MetadataError> {
+    if update.is_empty() {
+        return get_news_item(id, table_name, client).await;
+    }
+
+    // Publishing without a publish_at publishes a draft now, as `NewsUpdate::apply` does. The
+    // stored status is only known to DynamoDB, so the draft case is tried first under a
+    // condition, and anything that is not a draft gets the plain update.
+    if update.status == Some(NewsStatus::Published) && update.publish_at.is_none() {
+        let now = Utc::now().trunc_subsecs(3);
+        if let Some(item) = send_news_update(id, &update, Some(now), table_name, client).await? {
+            return Ok(item);
+        }
+    }
+    send_news_update(id, &update, None, table_name, client)
+        .await?
+        .ok_or_else(|| MetadataError::NotFound(format!("news item {}", id)))
+}
+
+pub async fn delete_news_item(
+    id: &str,
+    table_name: &str,
+    client: &DynamoClient,
+) -> Result<(), MetadataError> {
+    client
+        .delete_item()
+        .table_name(table_name)
+        .key("id", AttributeValue::S(id.to_string()))
+        .condition_expression("attribute_exists(id)")
+        .send()
+        .await
+        .map_err(|e| match e.into_service_error() {
+            DeleteItemError::ConditionalCheckFailedException(_) => {
+                MetadataError::NotFound(format!("news item {}", id))
+            }
+            other => MetadataError::Backend(anyhow!(aws_sdk_dynamodb::Error::from(other))),
+        })?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamoRepository {
+    client: DynamoClient,
+    tables: DynamoTables,
+}
+
+impl DynamoRepository {
+    pub fn new(client: DynamoClient, tables: DynamoTables) -> Self {
+        DynamoRepository { client, tables }
+    }
+
+    /// Backfills the news feed index keys of legacy news items. See `backfill_news_feed`.
+    pub async fn backfill_news_feed(&self) -> Result<usize, MetadataError> {
+        backfill_news_feed(&self.tables.news, &self.client).await
+    }
+}
+
+#[async_trait]
+impl MetadataRepository for DynamoRepository {
+    async fn get_issue(&self, issue_number: usize) -> Result<DBIssue, MetadataError> {
+        get_issue_data(issue_number, &self.tables.issues, &self.client).await
+    }
+
+    async fn list_issues(&self) -> Result<Vec<DBIssue>, MetadataError> {
+        list_issue_data(&self.tables.issues, &self.client).await
+    }
+
+    async fn put_issue(&self, issue: DBIssue) -> Result<(), MetadataError> {
+        put_issue_data(issue, &self.tables.issues, &self.client)
+            .await
+            .map_err(|e| MetadataError::Backend(e.into()))
+    }
+
+    async fn update_issue(
+        &self,
+        issue_number: usize,
+        update: IssueUpdate,
+    ) -> Result<DBIssue, MetadataError> {
+        update_issue_data(issue_number, update, &self.tables.issues, &self.client).await
+    }
+
+    async fn delete_issue(&self, issue_number: usize) -> Result<(), MetadataError> {
+        delete_issue_data(issue_number, &self.tables.issues, &self.client).await
+    }
+
+    async fn list_news(
+        &self,
+        limit: usize,
+        after: Option<NewsCursor>,
+        filter: NewsFilter,
+    ) -> Result<NewsPage, MetadataError> {
+        get_news_items(
+            limit,
+            after.as_ref(),
+            filter,
+            &self.tables.news,
+            &self.tables.news_index,
+            &self.client,
+        )
+        .await
+    }
+
+    async fn get_news(&self, id: &str) -> Result<NewsItem, MetadataError> {
+        get_news_item(id, &self.tables.news, &self.client).await
+    }
+
+    async fn create_news(&self, item: NewNewsItem) -> Result<NewsItem, MetadataError> {
+        create_news_item(item, &self.tables.news, &self.client).await
+    }
+
+    async fn update_news(&self, id: &str, update: NewsUpdate) -> Result<NewsItem, MetadataError> {
+        update_news_item(id, update, &self.tables.news, &self.client).await
+    }
+
+    async fn delete_news(&self, id: &str) -> Result<(), MetadataError> {
+        delete_news_item(id, &self.tables.news, &self.client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contributor(name: &str, handle: &str) -> DBContributor {
+        DBContributor {
+            name: name.to_string(),
+            handle: handle.to_string(),
+        }
+    }
+
+    fn parse_all(attribute: &AttributeValue) -> Vec<DBContributor> {
+        attribute
+            .as_l()
+            .unwrap()
+            .iter()
+            .map(|c| parse_contributor(c).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn contributors_round_trip_as_maps() {
+        let contributors = vec![
+            contributor("Ann Example", "ann"),
+            contributor("Same", "Same"),
+            contributor("Zed", "aardvark"),
+        ];
+        let attribute = contributors_attribute(&contributors);
+        assert!(attribute.as_l().unwrap().iter().all(|c| c.is_m()));
+
+        let parsed = parse_all(&attribute);
+        assert_eq!(parsed.len(), contributors.len());
+        for (parsed, original) in parsed.iter().zip(&contributors) {
+            assert_eq!(parsed.name, original.name);
+            assert_eq!(parsed.handle, original.handle);
+        }
+    }
+
+    #[test]
+    fn legacy_string_sets_are_still_read() {
+        let pair = AttributeValue::Ss(vec!["ann".to_string(), "Ann Example".to_string()]);
+        let parsed = parse_contributor(&pair).unwrap();
+        assert_eq!(
+            (parsed.handle.as_str(), parsed.name.as_str()),
+            ("ann", "Ann Example")
+        );
+
+        let deduplicated = AttributeValue::Ss(vec!["Same".to_string()]);
+        let parsed = parse_contributor(&deduplicated).unwrap();
+        assert_eq!(
+            (parsed.handle.as_str(), parsed.name.as_str()),
+            ("Same", "Same")
+        );
+    }
+
+    #[test]
+    fn malformed_contributors_are_rejected() {
+        let missing_handle = AttributeValue::M(HashMap::from([(
+            "name".to_string(),
+            AttributeValue::S("Ann".to_string()),
+        )]));
+        assert!(matches!(
+            parse_contributor(&missing_handle),
+            Err(MetadataError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse_contributor(&AttributeValue::S("ann".to_string())),
+            Err(MetadataError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+//! An in-process cache of the issue index, issue metadata and cover keys.
+//!
+//! `/count`, `/latest`, `/issues` and `/issuedata` all read from a shared `IssueSnapshot`
+//! instead of listing the blob store on every request. A background task started with
+//! `spawn_refresher` rebuilds the snapshot every half TTL, so in normal operation readers
+//! never wait on a listing. A snapshot older than the TTL (because refreshes are failing)
+//! is rebuilt on the next read.
+//!
+//! Only one rebuild runs at a time; readers that find the snapshot stale wait for it rather
+//! than starting their own. `invalidate` bumps a generation counter and rebuilds eagerly, and
+//! a rebuild that was already under way when the generation changed is discarded instead of
+//! installed, since it may have listed the store before the change.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+};
+
+use crate::{
+    error::ApiError,
+    utils::{
+        dynamodb::DBIssue,
+        metadata::MetadataRepository,
+        storage::{get_issue_index, BlobStore, IssueObject},
+    },
+};
+
+use super::get_cover_keys;
+
+/// Everything the issue routes need, captured at a single point in time.
+#[derive(Debug, Clone)]
+pub struct IssueSnapshot {
+    /// Every issue PDF in the store, sorted by ascending issue number.
+    pub index: Vec<IssueObject>,
+    /// Every issue record in the metadata repository, sorted by ascending issue number.
+    pub records: Vec<DBIssue>,
+    /// Cover image keys by issue number.
+    pub covers: HashMap<usize, String>,
+    fetched_at: Instant,
+    generation: u64,
+}
+
+impl IssueSnapshot {
+    pub fn record(&self, issue_number: usize) -> Option<&DBIssue> {
+        self.records
+            .binary_search_by_key(&issue_number, |issue| issue.number)
+            .ok()
+            .map(|i| &self.records[i])
+    }
+}
+
+pub struct IssueCache {
+    store: Arc<dyn BlobStore>,
+    repo: Arc<dyn MetadataRepository>,
+    ttl: Duration,
+    snapshot: RwLock<Option<Arc<IssueSnapshot>>>,
+    /// Bumped by `invalidate`; a snapshot built for an older generation is out of date.
+    generation: AtomicU64,
+    /// Held for the duration of a rebuild.
+    rebuild: Mutex<()>,
+}
+
+impl IssueCache {
+    pub fn new(
+        store: Arc<dyn BlobStore>,
+        repo: Arc<dyn MetadataRepository>,
+        ttl: Duration,
+    ) -> Self {
+        IssueCache {
+            store,
+            repo,
+            ttl,
+            snapshot: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            rebuild: Mutex::new(()),
+        }
+    }
+
+    /// Returns the cached snapshot, rebuilding it first if it is missing, invalidated or older
+    /// than the TTL.
+    pub async fn snapshot(&self) -> Result<Arc<IssueSnapshot>, ApiError> {
+        if let Some(snapshot) = self.current().await {
+            return Ok(snapshot);
+        }
+        let _rebuild = self.rebuild.lock().await;
+        // Another reader may have rebuilt the snapshot while this one waited for the lock
+        if let Some(snapshot) = self.current().await {
+            return Ok(snapshot);
+        }
+        self.rebuild_locked().await
+    }
+
+    /// Rebuilds the snapshot from the blob store and metadata repository.
+    pub async fn refresh(&self) -> Result<Arc<IssueSnapshot>, ApiError> {
+        let _rebuild = self.rebuild.lock().await;
+        self.rebuild_locked().await
+    }
+
+    /// Marks the cached snapshot out of date and rebuilds it. Called after the set of issues
+    /// changes. Until the rebuild finishes, readers keep the previous snapshot; if it fails,
+    /// the next read tries again.
+    pub async fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.refresh().await {
+            log::warn!("Could not rebuild issue cache after a change: {}", e);
+        }
+    }
+
+    /// The cached snapshot, if it is of the current generation and younger than the TTL.
+    async fn current(&self) -> Option<Arc<IssueSnapshot>> {
+        let snapshot = self.snapshot.read().await;
+        snapshot
+            .as_ref()
+            .filter(|s| {
+                s.generation == self.generation.load(Ordering::SeqCst)
+                    && s.fetched_at.elapsed() < self.ttl
+            })
+            .cloned()
+    }
+
+    /// Builds a new snapshot and installs it unless the cache was invalidated in the meantime.
+    /// The caller must hold `rebuild`.
+    async fn rebuild_locked(&self) -> Result<Arc<IssueSnapshot>, ApiError> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let (index, records, covers) = tokio::try_join!(
+            async { Ok::<_, ApiError>(get_issue_index(self.store.as_ref()).await?) },
+            async { Ok::<_, ApiError>(self.repo.list_issues().await?) },
+            get_cover_keys(self.store.as_ref()),
+        )?;
+        let snapshot = Arc::new(IssueSnapshot {
+            index,
+            records,
+            covers,
+            fetched_at: Instant::now(),
+            generation,
+        });
+        let mut slot = self.snapshot.write().await;
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *slot = Some(snapshot.clone());
+        }
+        Ok(snapshot)
+    }
+
+    /// Starts a task that keeps the snapshot warm, refreshing every half TTL.
+    pub fn spawn_refresher(self: Arc<Self>) -> JoinHandle<()> {
+        let period = (self.ttl / 2).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.refresh().await {
+                    log::warn!("Could not refresh issue cache: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::atomic::AtomicUsize};
+
+    use async_trait::async_trait;
+    use tempfile::TempDir;
+    use tokio::sync::watch;
+
+    use super::*;
+    use crate::{
+        testing::local_store,
+        utils::{
+            metadata::memory::InMemoryRepository,
+            storage::{issue_key, BlobInfo, StorageError},
+        },
+    };
+
+    /// A local store whose listings can be held at a gate after reading the directory, to
+    /// stop a rebuild part-way through.
+    struct GatedStore {
+        inner: Arc<dyn BlobStore>,
+        gate: watch::Sender<bool>,
+        lists: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl BlobStore for GatedStore {
+        async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>, StorageError> {
+            let blobs = self.inner.list(prefix).await;
+            self.lists.fetch_add(1, Ordering::SeqCst);
+            let _ = self.gate.subscribe().wait_for(|open| *open).await;
+            blobs
+        }
+
+        async fn put_file(&self, key: &str, path: &Path) -> Result<(), StorageError> {
+            self.inner.put_file(key, path).await
+        }
+
+        async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StorageError> {
+            self.inner.delete(key).await
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+            self.inner.get(key).await
+        }
+
+        async fn signed_url(&self, key: &str, ttl: Duration) -> Result<String, StorageError> {
+            self.inner.signed_url(key, ttl).await
+        }
+    }
+
+    struct Fixture {
+        root: TempDir,
+        store: Arc<GatedStore>,
+        cache: Arc<IssueCache>,
+    }
+
+    impl Fixture {
+        fn new(ttl: Duration) -> Self {
+            let root = tempfile::tempdir().unwrap();
+            let store = Arc::new(GatedStore {
+                inner: local_store(root.path()),
+                gate: watch::Sender::new(true),
+                lists: AtomicUsize::new(0),
+            });
+            let repo = Arc::new(InMemoryRepository::new());
+            let cache = Arc::new(IssueCache::new(store.clone(), repo, ttl));
+            Fixture { root, store, cache }
+        }
+
+        async fn publish(&self, issue_number: usize) {
+            let source = self.root.path().join("source.pdf");
+            std::fs::write(&source, b"%PDF-1.5").unwrap();
+            self.store
+                .put_file(&issue_key(issue_number), &source)
+                .await
+                .unwrap();
+        }
+
+        fn lists(&self) -> usize {
+            self.store.lists.load(Ordering::SeqCst)
+        }
+    }
+
+    fn numbers(snapshot: &IssueSnapshot) -> Vec<usize> {
+        snapshot.index.iter().map(|issue| issue.number).collect()
+    }
+
+    #[tokio::test]
+    async fn a_rebuild_invalidated_mid_flight_is_not_installed() {
+        let fixture = Fixture::new(Duration::from_secs(60));
+        fixture.publish(1).await;
+        let first = fixture.cache.snapshot().await.unwrap();
+        assert_eq!(numbers(&first), [1]);
+
+        // Hold a refresh after it has listed the store, then change the store underneath it
+        fixture.store.gate.send_replace(false);
+        let lists = fixture.lists();
+        let refresh = tokio::spawn({
+            let cache = fixture.cache.clone();
+            async move { cache.refresh().await }
+        });
+        while fixture.lists() < lists + 2 {
+            tokio::task::yield_now().await;
+        }
+        fixture.publish(2).await;
+        fixture.cache.generation.fetch_add(1, Ordering::SeqCst);
+        fixture.store.gate.send_replace(true);
+
+        // The stale rebuild is returned to its caller but not installed
+        let stale = refresh.await.unwrap().unwrap();
+        assert_eq!(numbers(&stale), [1]);
+        let installed = fixture.cache.snapshot.read().await.clone().unwrap();
+        assert!(Arc::ptr_eq(&installed, &first));
+
+        let fresh = fixture.cache.snapshot().await.unwrap();
+        assert_eq!(numbers(&fresh), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn invalidate_rebuilds_at_once() {
+        let fixture = Fixture::new(Duration::from_secs(60));
+        fixture.publish(1).await;
+        fixture.cache.snapshot().await.unwrap();
+
+        fixture.publish(2).await;
+        assert_eq!(numbers(&fixture.cache.snapshot().await.unwrap()), [1]);
+        fixture.cache.invalidate().await;
+        let lists = fixture.lists();
+        assert_eq!(numbers(&fixture.cache.snapshot().await.unwrap()), [1, 2]);
+        assert_eq!(fixture.lists(), lists);
+    }
+
+    #[tokio::test]
+    async fn an_expired_snapshot_is_rebuilt_on_read() {
+        let ttl = Duration::from_millis(100);
+        let fixture = Fixture::new(ttl);
+        fixture.publish(1).await;
+        let first = fixture.cache.snapshot().await.unwrap();
+        let lists = fixture.lists();
+
+        // Within the TTL the cached snapshot is served without listing the store
+        fixture.publish(2).await;
+        let cached = fixture.cache.snapshot().await.unwrap();
+        assert!(Arc::ptr_eq(&cached, &first));
+        assert_eq!(fixture.lists(), lists);
+
+        tokio::time::sleep(ttl).await;
+        let rebuilt = fixture.cache.snapshot().await.unwrap();
+        assert_eq!(numbers(&rebuilt), [1, 2]);
+        assert_eq!(fixture.lists(), lists + 2);
+    }
+}
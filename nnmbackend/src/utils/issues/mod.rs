@@ -0,0 +1,338 @@
+//! This module builds the issue catalog served by `GET /issues`.
+//!
+//! The catalog joins the issue index from the blob store (which issues exist, their size and
+//! when they were uploaded) with the issue records from the metadata repository (blurb and
+//! contributors), newest issue first. Cover images are optional and live under `nnm_covers/`
+//! as `issue_{n}.{ext}`.
+//!
+//! # Structs
+//!
+//! - `CatalogQuery`: Paging and filtering options for a catalog request.
+//! - `CatalogEntry`: A single issue in the catalog.
+//! - `CatalogPage`: One page of entries plus the cursor for the next page.
+//! - `IssueCache` (in `cache`): Keeps the index, records and cover keys in memory.
+//!
+//! # Functions
+//!
+//! - `get_issue_catalog`: Builds one page of the catalog from an `IssueSnapshot`.
+//!
+//! # Errors
+//!
+//! `get_issue_catalog` does not fail. An issue whose cover URL cannot be signed is listed
+//! without one, and issues without a metadata record are listed with no blurb or contributors.
+
+pub mod cache;
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+
+use crate::error::ApiError;
+
+use self::cache::IssueSnapshot;
+use super::{
+    dynamodb::{DBContributor, DBIssue},
+    storage::{BlobStore, IssueObject},
+};
+
+/// The prefix optional cover images are stored under.
+pub const COVER_PREFIX: &str = "nnm_covers/";
+
+#[derive(Debug, Clone, Default)]
+pub struct CatalogQuery {
+    pub limit: usize,
+    /// Only return issues older than this issue number.
+    pub cursor: Option<usize>,
+    /// Only return issues with a contributor using this handle.
+    pub contributor: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CatalogEntry {
+    pub number: usize,
+    pub blurb: Option<String>,
+    pub contributors: Vec<DBContributor>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub file_size: u64,
+    pub cover_url: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CatalogPage {
+    pub issues: Vec<CatalogEntry>,
+    pub next_cursor: Option<String>,
+}
+
+/// Maps issue numbers to cover image keys such as `nnm_covers/issue_3.jpg`.
+async fn get_cover_keys(store: &dyn BlobStore) -> Result<HashMap<usize, String>, ApiError> {
+    Ok(store
+        .list(COVER_PREFIX)
+        .await?
+        .into_iter()
+        .filter_map(|obj| {
+            let name = obj.key.rsplit('/').next()?;
+            let (stem, _extension) = name.rsplit_once('.')?;
+            let number = stem.strip_prefix("issue_")?.parse::<usize>().ok()?;
+            Some((number, obj.key))
+        })
+        .collect())
+}
+
+/// Joins `index` with `records`, newest first, and applies the cursor, filter and limit.
+///
+/// Returns the selected entries (without cover URLs) and whether more entries remain.
+fn select_entries(
+    index: &[IssueObject],
+    records: &[DBIssue],
+    query: &CatalogQuery,
+) -> (Vec<CatalogEntry>, bool) {
+    let records: HashMap<usize, &DBIssue> =
+        records.iter().map(|issue| (issue.number, issue)).collect();
+
+    let mut matching = index
+        .iter()
+        .rev()
+        .filter(|issue| query.cursor.is_none_or(|cursor| issue.number < cursor))
+        .map(|issue| {
+            let record = records.get(&issue.number);
+            CatalogEntry {
+                number: issue.number,
+                blurb: record.map(|r| r.blurb.clone()),
+                contributors: record.map(|r| r.contributors.clone()).unwrap_or_default(),
+                published_at: issue.last_modified,
+                file_size: issue.size,
+                cover_url: None,
+            }
+        })
+        .filter(|entry| {
+            query
+                .contributor
+                .as_ref()
+                .is_none_or(|handle| entry.contributors.iter().any(|c| &c.handle == handle))
+        });
+
+    let entries: Vec<CatalogEntry> = matching.by_ref().take(query.limit).collect();
+    let has_more = matching.next().is_some();
+    (entries, has_more)
+}
+
+/// Signs the URL of one issue's cover. If it cannot be signed, a warning is logged and the
+/// issue is listed without a cover, so one bad cover does not hide the rest of the catalog.
+async fn sign_cover(
+    store: &dyn BlobStore,
+    issue_number: usize,
+    key: &str,
+    presign_ttl: Duration,
+) -> Option<String> {
+    match store.signed_url(key, presign_ttl).await {
+        Ok(url) => Some(url),
+        Err(e) => {
+            log::warn!(
+                "Could not sign cover {} for issue {}: {}",
+                key,
+                issue_number,
+                e
+            );
+            None
+        }
+    }
+}
+
+pub async fn get_issue_catalog(
+    store: &dyn BlobStore,
+    snapshot: &IssueSnapshot,
+    query: &CatalogQuery,
+    presign_ttl: Duration,
+) -> CatalogPage {
+    let (mut issues, has_more) = select_entries(&snapshot.index, &snapshot.records, query);
+
+    // Presign every cover at once; join_all keeps the catalog order
+    let cover_urls = join_all(issues.iter().map(|entry| async move {
+        let key = snapshot.covers.get(&entry.number)?;
+        sign_cover(store, entry.number, key, presign_ttl).await
+    }))
+    .await;
+    for (entry, cover_url) in issues.iter_mut().zip(cover_urls) {
+        entry.cover_url = cover_url;
+    }
+
+    let next_cursor = if has_more {
+        issues.last().map(|entry| entry.number.to_string())
+    } else {
+        None
+    };
+    CatalogPage {
+        issues,
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        testing::{local_store, Faults, FaultyStore},
+        utils::{
+            issues::cache::IssueCache,
+            metadata::{memory::InMemoryRepository, MetadataRepository},
+            storage::issue_key,
+        },
+    };
+
+    fn object(number: usize) -> IssueObject {
+        IssueObject {
+            number,
+            key: format!("nnm_issues/issue_{}.pdf", number),
+            size: number as u64 * 1000,
+            last_modified: None,
+        }
+    }
+
+    fn record(number: usize, handles: &[&str]) -> DBIssue {
+        let contributors = handles
+            .iter()
+            .map(|handle| DBContributor {
+                name: handle.to_uppercase(),
+                handle: handle.to_string(),
+            })
+            .collect();
+        DBIssue::new(number, format!("Issue {}", number), contributors)
+    }
+
+    fn numbers(entries: &[CatalogEntry]) -> Vec<usize> {
+        entries.iter().map(|entry| entry.number).collect()
+    }
+
+    fn query(limit: usize, cursor: Option<usize>, contributor: Option<&str>) -> CatalogQuery {
+        CatalogQuery {
+            limit,
+            cursor,
+            contributor: contributor.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn entries_are_newest_first_and_limited() {
+        let index: Vec<IssueObject> = (1..=5).map(object).collect();
+
+        let (entries, has_more) = select_entries(&index, &[], &query(3, None, None));
+        assert_eq!(numbers(&entries), vec![5, 4, 3]);
+        assert!(has_more);
+
+        let (entries, has_more) = select_entries(&index, &[], &query(5, None, None));
+        assert_eq!(numbers(&entries), vec![5, 4, 3, 2, 1]);
+        assert!(!has_more);
+    }
+
+    #[test]
+    fn cursor_pages_through_the_whole_catalog() {
+        let index: Vec<IssueObject> = (1..=5).map(object).collect();
+
+        let (first, has_more) = select_entries(&index, &[], &query(2, None, None));
+        assert_eq!(numbers(&first), vec![5, 4]);
+        assert!(has_more);
+
+        let (second, has_more) = select_entries(&index, &[], &query(2, Some(4), None));
+        assert_eq!(numbers(&second), vec![3, 2]);
+        assert!(has_more);
+
+        let (last, has_more) = select_entries(&index, &[], &query(2, Some(2), None));
+        assert_eq!(numbers(&last), vec![1]);
+        assert!(!has_more);
+
+        let (past_end, has_more) = select_entries(&index, &[], &query(2, Some(1), None));
+        assert!(past_end.is_empty());
+        assert!(!has_more);
+    }
+
+    #[test]
+    fn contributor_filter_applies_before_the_limit() {
+        let index: Vec<IssueObject> = (1..=6).map(object).collect();
+        let records = vec![
+            record(1, &["ada"]),
+            record(2, &["bob"]),
+            record(3, &["ada", "bob"]),
+            record(4, &["bob"]),
+            record(5, &["ada"]),
+        ];
+
+        let (first, has_more) = select_entries(&index, &records, &query(2, None, Some("ada")));
+        assert_eq!(numbers(&first), vec![5, 3]);
+        assert!(has_more);
+
+        let (rest, has_more) = select_entries(&index, &records, &query(2, Some(3), Some("ada")));
+        assert_eq!(numbers(&rest), vec![1]);
+        assert!(!has_more);
+
+        let (none, has_more) = select_entries(&index, &records, &query(2, None, Some("eve")));
+        assert!(none.is_empty());
+        assert!(!has_more);
+    }
+
+    #[test]
+    fn issues_without_a_record_are_listed_without_metadata() {
+        let index = vec![object(1), object(2)];
+        let records = vec![record(1, &["ada"]), record(7, &["bob"])];
+
+        let (entries, _) = select_entries(&index, &records, &query(10, None, None));
+        assert_eq!(numbers(&entries), vec![2, 1]);
+        assert_eq!(entries[0].blurb, None);
+        assert!(entries[0].contributors.is_empty());
+        assert_eq!(entries[0].file_size, 2000);
+        assert_eq!(entries[1].blurb.as_deref(), Some("Issue 1"));
+        assert_eq!(entries[1].contributors[0].handle, "ada");
+    }
+
+    /// A catalog of issues 1 and 2 where only issue 2 has a cover, read through `faults`.
+    async fn catalog(faults: Faults) -> CatalogPage {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("source");
+        std::fs::write(&source, b"%PDF-1.5").unwrap();
+        let local = local_store(root.path());
+        for key in [
+            issue_key(1),
+            issue_key(2),
+            format!("{}issue_2.png", COVER_PREFIX),
+        ] {
+            local.put_file(&key, &source).await.unwrap();
+        }
+        let repo = Arc::new(InMemoryRepository::new());
+        repo.put_issue(record(2, &["ada"])).await.unwrap();
+
+        let store: Arc<dyn BlobStore> = Arc::new(FaultyStore::new(local, faults));
+        let cache = IssueCache::new(store.clone(), repo, Duration::from_secs(60));
+        let snapshot = cache.snapshot().await.unwrap();
+        get_issue_catalog(
+            store.as_ref(),
+            &snapshot,
+            &query(10, None, None),
+            Duration::from_secs(60),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn catalog_signs_covers_in_order() {
+        let page = catalog(Faults::default()).await;
+        assert_eq!(numbers(&page.issues), vec![2, 1]);
+        let cover = page.issues[0].cover_url.as_deref().unwrap();
+        assert!(cover.starts_with("http://localhost/blob/nnm_covers/issue_2.png?"));
+        assert_eq!(page.issues[1].cover_url, None);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn a_cover_that_cannot_be_signed_is_left_out() {
+        let faults = Faults {
+            sign: true,
+            ..Faults::default()
+        };
+        let page = catalog(faults).await;
+        assert_eq!(numbers(&page.issues), vec![2, 1]);
+        assert!(page.issues.iter().all(|entry| entry.cover_url.is_none()));
+        assert_eq!(page.issues[0].blurb.as_deref(), Some("Issue 2"));
+    }
+}
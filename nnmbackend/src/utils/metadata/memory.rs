@@ -0,0 +1,157 @@
+//! A `MetadataRepository` that keeps issues and news in process memory.
+//!
+//! Useful for integration tests and quick local runs; all data is lost when the server stops.
+//! News times are kept to the millisecond, as the other backends store them, so a `NewsCursor`
+//! names an item exactly and paging behaves the same on every backend.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::SubsecRound;
+use tokio::sync::RwLock;
+
+use super::{
+    IssueUpdate, MetadataError, MetadataRepository, NewNewsItem, NewsCursor, NewsFilter, NewsPage,
+    NewsUpdate,
+};
+use crate::utils::{dynamodb::DBIssue, news::NewsItem};
+
+#[derive(Debug, Default)]
+pub struct InMemoryRepository {
+    issues: RwLock<BTreeMap<usize, DBIssue>>,
+    news: RwLock<Vec<NewsItem>>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Drops any precision finer than a millisecond from an item's times.
+fn truncate_times(item: &mut NewsItem) {
+    item.published_at = item.published_at.trunc_subsecs(3);
+    item.publish_at = item
+        .publish_at
+        .map(|publish_at| publish_at.trunc_subsecs(3));
+}
+
+#[async_trait]
+impl MetadataRepository for InMemoryRepository {
+    async fn get_issue(&self, issue_number: usize) -> Result<DBIssue, MetadataError> {
+        self.issues
+            .read()
+            .await
+            .get(&issue_number)
+            .cloned()
+            .ok_or(MetadataError::NotFound(format!("issue {}", issue_number)))
+    }
+
+    async fn list_issues(&self) -> Result<Vec<DBIssue>, MetadataError> {
+        Ok(self.issues.read().await.values().cloned().collect())
+    }
+
+    async fn put_issue(&self, issue: DBIssue) -> Result<(), MetadataError> {
+        self.issues.write().await.insert(issue.number, issue);
+        Ok(())
+    }
+
+    async fn update_issue(
+        &self,
+        issue_number: usize,
+        update: IssueUpdate,
+    ) -> Result<DBIssue, MetadataError> {
+        let mut issues = self.issues.write().await;
+        let issue = issues
+            .get_mut(&issue_number)
+            .ok_or(MetadataError::NotFound(format!("issue {}", issue_number)))?;
+        update.apply(issue);
+        Ok(issue.clone())
+    }
+
+    async fn delete_issue(&self, issue_number: usize) -> Result<(), MetadataError> {
+        self.issues
+            .write()
+            .await
+            .remove(&issue_number)
+            .map(|_| ())
+            .ok_or(MetadataError::NotFound(format!("issue {}", issue_number)))
+    }
+
+    async fn list_news(
+        &self,
+        limit: usize,
+        after: Option<NewsCursor>,
+        filter: NewsFilter,
+    ) -> Result<NewsPage, MetadataError> {
+        let mut items: Vec<NewsItem> = self
+            .news
+            .read()
+            .await
+            .iter()
+            .filter(|item| filter.matches(item))
+            .filter(|item| {
+                after.as_ref().is_none_or(|cursor| {
+                    (item.published_at, &item.id) < (cursor.published_at, &cursor.id)
+                })
+            })
+            .cloned()
+            .collect();
+        items.sort_by(|a, b| (b.published_at, &b.id).cmp(&(a.published_at, &a.id)));
+        let has_more = items.len() > limit;
+        items.truncate(limit);
+        let next_cursor = if has_more {
+            items.last().map(NewsCursor::after)
+        } else {
+            None
+        };
+        Ok(NewsPage { items, next_cursor })
+    }
+
+    async fn get_news(&self, id: &str) -> Result<NewsItem, MetadataError> {
+        self.news
+            .read()
+            .await
+            .iter()
+            .find(|item| item.id == id)
+            .cloned()
+            .ok_or(MetadataError::NotFound(format!("news item {}", id)))
+    }
+
+    async fn create_news(&self, item: NewNewsItem) -> Result<NewsItem, MetadataError> {
+        let mut item = NewsItem {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            title: item.title,
+            description: item.description,
+            image_name: item.image_name,
+            status: item.status,
+            published_at: item.published_at,
+            publish_at: item.publish_at,
+            image_url: None,
+        };
+        truncate_times(&mut item);
+        self.news.write().await.push(item.clone());
+        Ok(item)
+    }
+
+    async fn update_news(&self, id: &str, update: NewsUpdate) -> Result<NewsItem, MetadataError> {
+        let mut news = self.news.write().await;
+        let item = news
+            .iter_mut()
+            .find(|item| item.id == id)
+            .ok_or(MetadataError::NotFound(format!("news item {}", id)))?;
+        update.apply(item);
+        truncate_times(item);
+        Ok(item.clone())
+    }
+
+    async fn delete_news(&self, id: &str) -> Result<(), MetadataError> {
+        let mut news = self.news.write().await;
+        let index = news
+            .iter()
+            .position(|item| item.id == id)
+            .ok_or(MetadataError::NotFound(format!("news item {}", id)))?;
+        news.remove(index);
+        Ok(())
+    }
+}
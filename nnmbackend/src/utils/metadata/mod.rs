@@ -0,0 +1,554 @@
+//! This module provides a backend-agnostic interface for issue and news metadata.
+//!
+//! Routes talk to a `MetadataRepository` trait object rather than to DynamoDB directly, so
+//! developers and integration tests can run the whole API offline. The backend is chosen
+//! once at startup.
+//!
+//! # Traits
+//!
+//! - `MetadataRepository`: Reads, lists, writes, updates and deletes issue records and news items.
+//!
+//! # Structs
+//!
+//! - `IssueUpdate`: The fields of an issue record to change in `update_issue`.
+//! - `NewNewsItem`: A news item to create; the repository assigns its id.
+//! - `NewsUpdate`: The fields of a news item to change in `update_news`.
+//! - `NewsCursor`: A position in the news feed, passed to `list_news` to fetch the next page.
+//! - `NewsFilter`: Which items `list_news` returns: only live ones, or drafts and scheduled items too.
+//! - `NewsPage`: One page of news items plus the cursor for the next page.
+//!
+//! # Implementations
+//!
+//! - `DynamoRepository` (in `utils::dynamodb`): The production backend, using the `nnmIssueData` and `nnmNews` tables.
+//! - `InMemoryRepository` (in `memory`): Keeps everything in process memory; nothing survives a restart.
+//! - `SqliteRepository` (in `sqlite`): Stores everything in an embedded SQLite database file.
+//!
+//! # Errors
+//!
+//! All operations return a `MetadataError`, which distinguishes missing records from backend failures.
+
+pub mod memory;
+pub mod sqlite;
+
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+
+use super::{
+    dynamodb::{DBContributor, DBIssue},
+    news::{NewsItem, NewsStatus},
+};
+
+/// How many news items the news feed returns when no limit is given.
+pub const LATEST_NEWS_LIMIT: usize = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("Record not found: {0}")]
+    NotFound(String),
+    #[error("Malformed record: {0}")]
+    Malformed(String),
+    #[error("Metadata backend error: {0}")]
+    Backend(#[from] anyhow::Error),
+}
+
+/// A partial update to an issue record; fields left as `None` are unchanged.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct IssueUpdate {
+    pub blurb: Option<String>,
+    pub contributors: Option<Vec<DBContributor>>,
+}
+
+impl IssueUpdate {
+    pub fn apply(self, issue: &mut DBIssue) {
+        if let Some(blurb) = self.blurb {
+            issue.blurb = blurb;
+        }
+        if let Some(contributors) = self.contributors {
+            issue.contributors = contributors;
+        }
+    }
+}
+
+/// A news item to create with `create_news`.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct NewNewsItem {
+    pub title: String,
+    pub description: String,
+    pub image_name: String,
+    pub status: NewsStatus,
+    pub published_at: DateTime<Utc>,
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+/// A partial update to a news item; fields left as `None` are unchanged.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct NewsUpdate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_name: Option<String>,
+    /// Publishing a draft without a `publish_at` publishes it now; see `apply`.
+    pub status: Option<NewsStatus>,
+    /// Reschedules the item, which also moves it to that time in the feed.
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+impl NewsUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.image_name.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+    }
+
+    /// Whether this update publishes `item` immediately: it moves a draft to `published`
+    /// without giving a `publish_at`.
+    pub fn publishes_now(&self, item: &NewsItem) -> bool {
+        item.status == NewsStatus::Draft
+            && self.status == Some(NewsStatus::Published)
+            && self.publish_at.is_none()
+    }
+
+    /// Applies the update to `item`. A draft published without a `publish_at` moves to the top
+    /// of the feed with `published_at` set to now, rather than keeping the time it was drafted,
+    /// and loses any schedule it had.
+    pub fn apply(self, item: &mut NewsItem) {
+        if self.publishes_now(item) {
+            item.published_at = Utc::now().trunc_subsecs(3);
+            item.publish_at = None;
+        }
+        if let Some(title) = self.title {
+            item.title = title;
+        }
+        if let Some(description) = self.description {
+            item.description = description;
+        }
+        if let Some(image_name) = self.image_name {
+            item.image_name = image_name;
+        }
+        if let Some(status) = self.status {
+            item.status = status;
+        }
+        if let Some(publish_at) = self.publish_at {
+            item.publish_at = Some(publish_at);
+            item.published_at = publish_at;
+        }
+    }
+}
+
+/// The feed position just after a given item. The feed is ordered by `published_at`, newest
+/// first, with ties broken by descending id.
+///
+/// Cursors are written as `<published_at in Unix milliseconds>.<id>`, so `published_at` must
+/// be stored with millisecond precision for a cursor to name an item exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewsCursor {
+    pub published_at: DateTime<Utc>,
+    pub id: String,
+}
+
+impl NewsCursor {
+    pub fn after(item: &NewsItem) -> Self {
+        NewsCursor {
+            published_at: item.published_at,
+            id: item.id.clone(),
+        }
+    }
+}
+
+impl fmt::Display for NewsCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.published_at.timestamp_millis(), self.id)
+    }
+}
+
+impl FromStr for NewsCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cursor {:?}", s);
+        let (millis, id) = s.split_once('.').ok_or_else(invalid)?;
+        let published_at = millis
+            .parse::<i64>()
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(invalid)?;
+        if id.is_empty() {
+            return Err(invalid());
+        }
+        Ok(NewsCursor {
+            published_at,
+            id: id.to_string(),
+        })
+    }
+}
+
+/// Which news items `list_news` returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewsFilter {
+    /// Published items whose `published_at` is no later than the given time: the public feed.
+    LiveAt(DateTime<Utc>),
+    /// Every item, including drafts and items scheduled for later.
+    All,
+}
+
+impl NewsFilter {
+    pub fn matches(self, item: &NewsItem) -> bool {
+        match self {
+            NewsFilter::LiveAt(now) => item.is_live(now),
+            NewsFilter::All => true,
+        }
+    }
+}
+
+/// One page of the news feed, newest first.
+#[derive(Debug, Clone)]
+pub struct NewsPage {
+    pub items: Vec<NewsItem>,
+    /// Where the next page starts, or `None` if this is the last page.
+    pub next_cursor: Option<NewsCursor>,
+}
+
+#[async_trait]
+pub trait MetadataRepository: Send + Sync {
+    /// Fetches the record for a single issue.
+    async fn get_issue(&self, issue_number: usize) -> Result<DBIssue, MetadataError>;
+
+    /// Fetches every issue record, sorted by ascending issue number.
+    async fn list_issues(&self) -> Result<Vec<DBIssue>, MetadataError>;
+
+    /// Stores the record for an issue, replacing any existing record with the same number.
+    async fn put_issue(&self, issue: DBIssue) -> Result<(), MetadataError>;
+
+    /// Applies `update` to an existing issue record and returns the updated record.
+    async fn update_issue(
+        &self,
+        issue_number: usize,
+        update: IssueUpdate,
+    ) -> Result<DBIssue, MetadataError>;
+
+    /// Removes the record for an issue, returning `MetadataError::NotFound` if there is none.
+    async fn delete_issue(&self, issue_number: usize) -> Result<(), MetadataError>;
+
+    /// Fetches up to `limit` news items matching `filter`, newest first, starting after `after`
+    /// if it is given.
+    async fn list_news(
+        &self,
+        limit: usize,
+        after: Option<NewsCursor>,
+        filter: NewsFilter,
+    ) -> Result<NewsPage, MetadataError>;
+
+    /// Fetches a single news item.
+    async fn get_news(&self, id: &str) -> Result<NewsItem, MetadataError>;
+
+    /// Stores a new news item and returns it with its assigned id.
+    async fn create_news(&self, item: NewNewsItem) -> Result<NewsItem, MetadataError>;
+
+    /// Applies `update` to an existing news item and returns the updated item.
+    async fn update_news(&self, id: &str, update: NewsUpdate) -> Result<NewsItem, MetadataError>;
+
+    /// Removes a news item, returning `MetadataError::NotFound` if there is none.
+    async fn delete_news(&self, id: &str) -> Result<(), MetadataError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, SubsecRound, TimeZone};
+    use serde_json::Value;
+
+    use super::{memory::InMemoryRepository, sqlite::SqliteRepository, *};
+
+    fn json(value: &impl serde::Serialize) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn issue(number: usize, blurb: &str) -> DBIssue {
+        DBIssue::new(
+            number,
+            blurb.to_string(),
+            vec![DBContributor {
+                name: "Ann Example".to_string(),
+                handle: "ann".to_string(),
+            }],
+        )
+    }
+
+    fn new_item(title: &str, published_at: DateTime<Utc>, status: NewsStatus) -> NewNewsItem {
+        NewNewsItem {
+            title: title.to_string(),
+            description: format!("About {}", title),
+            image_name: format!("nnm_news/{}.png", title),
+            status,
+            published_at,
+            publish_at: None,
+        }
+    }
+
+    /// A time with sub-millisecond precision, which backends only keep to the millisecond.
+    fn time(millis: i64, micros: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+            + Duration::milliseconds(millis)
+            + Duration::microseconds(micros)
+    }
+
+    async fn issues_round_trip(repo: &dyn MetadataRepository) {
+        assert!(matches!(
+            repo.get_issue(1).await,
+            Err(MetadataError::NotFound(_))
+        ));
+
+        repo.put_issue(issue(2, "Second")).await.unwrap();
+        repo.put_issue(issue(1, "First")).await.unwrap();
+        assert_eq!(
+            json(&repo.get_issue(1).await.unwrap()),
+            json(&issue(1, "First"))
+        );
+        let numbers: Vec<_> = repo
+            .list_issues()
+            .await
+            .unwrap()
+            .iter()
+            .map(|issue| issue.number)
+            .collect();
+        assert_eq!(numbers, [1, 2]);
+
+        repo.put_issue(issue(1, "Replaced")).await.unwrap();
+        assert_eq!(repo.get_issue(1).await.unwrap().blurb, "Replaced");
+
+        let update = IssueUpdate {
+            blurb: Some("Updated".to_string()),
+            contributors: None,
+        };
+        let updated = repo.update_issue(1, update.clone()).await.unwrap();
+        assert_eq!(updated.blurb, "Updated");
+        assert_eq!(updated.contributors[0].handle, "ann");
+        assert_eq!(json(&repo.get_issue(1).await.unwrap()), json(&updated));
+        assert!(matches!(
+            repo.update_issue(3, update).await,
+            Err(MetadataError::NotFound(_))
+        ));
+
+        repo.delete_issue(1).await.unwrap();
+        assert!(matches!(
+            repo.get_issue(1).await,
+            Err(MetadataError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_issue(1).await,
+            Err(MetadataError::NotFound(_))
+        ));
+        assert_eq!(repo.list_issues().await.unwrap().len(), 1);
+    }
+
+    async fn news_round_trip(repo: &dyn MetadataRepository) {
+        let created = repo
+            .create_news(new_item("launch", time(0, 0), NewsStatus::Published))
+            .await
+            .unwrap();
+        assert!(!created.id.is_empty());
+        assert_eq!(
+            json(&repo.get_news(&created.id).await.unwrap()),
+            json(&created)
+        );
+
+        let scheduled = time(60_000, 250);
+        let update = NewsUpdate {
+            title: Some("Relaunch".to_string()),
+            status: Some(NewsStatus::Draft),
+            publish_at: Some(scheduled),
+            ..NewsUpdate::default()
+        };
+        let updated = repo.update_news(&created.id, update).await.unwrap();
+        assert_eq!(updated.title, "Relaunch");
+        assert_eq!(updated.description, created.description);
+        assert_eq!(updated.status, NewsStatus::Draft);
+        assert_eq!(updated.published_at, scheduled.trunc_subsecs(3));
+        assert_eq!(updated.publish_at, Some(scheduled.trunc_subsecs(3)));
+        assert_eq!(
+            json(&repo.get_news(&created.id).await.unwrap()),
+            json(&updated)
+        );
+
+        repo.delete_news(&created.id).await.unwrap();
+        assert!(matches!(
+            repo.get_news(&created.id).await,
+            Err(MetadataError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_news(&created.id).await,
+            Err(MetadataError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.update_news(&created.id, NewsUpdate::default()).await,
+            Err(MetadataError::NotFound(_))
+        ));
+    }
+
+    async fn news_publishing(repo: &dyn MetadataRepository) {
+        let mut draft = new_item("draft", time(0, 0), NewsStatus::Draft);
+        draft.publish_at = Some(time(0, 0));
+        let draft = repo.create_news(draft).await.unwrap();
+        let older = repo
+            .create_news(new_item("older", time(1_000, 0), NewsStatus::Published))
+            .await
+            .unwrap();
+
+        // Published without a schedule, the draft moves to the top of the feed
+        let publish = NewsUpdate {
+            status: Some(NewsStatus::Published),
+            ..NewsUpdate::default()
+        };
+        let before = Utc::now().trunc_subsecs(3);
+        let published = repo.update_news(&draft.id, publish.clone()).await.unwrap();
+        assert_eq!(published.status, NewsStatus::Published);
+        assert!(published.published_at >= before);
+        assert!(published.published_at <= Utc::now());
+        assert_eq!(published.publish_at, None);
+        assert_eq!(
+            json(&repo.get_news(&draft.id).await.unwrap()),
+            json(&published)
+        );
+        let feed = repo
+            .list_news(10, None, NewsFilter::LiveAt(Utc::now()))
+            .await
+            .unwrap();
+        let ids: Vec<_> = feed.items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, [draft.id.as_str(), older.id.as_str()]);
+
+        // Publishing an item that is already published leaves it where it is
+        let republished = repo.update_news(&older.id, publish).await.unwrap();
+        assert_eq!(republished.published_at, older.published_at);
+
+        // A draft published with a schedule goes out at that time instead
+        let scheduled = time(7_200_000, 0);
+        let draft = repo
+            .create_news(new_item("scheduled", time(2_000, 0), NewsStatus::Draft))
+            .await
+            .unwrap();
+        let update = NewsUpdate {
+            status: Some(NewsStatus::Published),
+            publish_at: Some(scheduled),
+            ..NewsUpdate::default()
+        };
+        let updated = repo.update_news(&draft.id, update).await.unwrap();
+        assert_eq!(updated.published_at, scheduled);
+        assert_eq!(updated.publish_at, Some(scheduled));
+    }
+
+    /// Pages through the feed one item at a time, passing each cursor through its string form
+    /// as `GET /news` does.
+    async fn page_ids(repo: &dyn MetadataRepository, filter: NewsFilter) -> Vec<String> {
+        let mut ids = Vec::new();
+        let mut cursor: Option<NewsCursor> = None;
+        loop {
+            let page = repo.list_news(1, cursor, filter).await.unwrap();
+            ids.extend(page.items.iter().map(|item| item.id.clone()));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next.to_string().parse().unwrap()),
+                None => return ids,
+            }
+        }
+    }
+
+    async fn news_pages(repo: &dyn MetadataRepository) {
+        // Several items share a millisecond, so cursors must compare at that precision
+        let mut created = Vec::new();
+        for (title, millis, micros, status) in [
+            ("a", 0, 100, NewsStatus::Published),
+            ("b", 0, 900, NewsStatus::Published),
+            ("c", 0, 500, NewsStatus::Published),
+            ("d", 1, 0, NewsStatus::Draft),
+            ("e", 2, 999, NewsStatus::Published),
+            ("f", 3_600_000, 0, NewsStatus::Published),
+        ] {
+            let item = new_item(title, time(millis, micros), status);
+            created.push(repo.create_news(item).await.unwrap());
+        }
+        for item in &created {
+            assert_eq!(item.published_at, item.published_at.trunc_subsecs(3));
+        }
+        let mut expected = created.clone();
+        expected.sort_by(|a, b| (b.published_at, &b.id).cmp(&(a.published_at, &a.id)));
+        let expected: Vec<_> = expected.into_iter().map(|item| item.id).collect();
+        assert_eq!(page_ids(repo, NewsFilter::All).await, expected);
+
+        let page = repo.list_news(10, None, NewsFilter::All).await.unwrap();
+        assert_eq!(page.items.len(), created.len());
+        assert!(page.next_cursor.is_none());
+
+        // "d" is a draft and "f" is not yet due
+        let now = time(60_000, 0);
+        let live: Vec<_> = expected
+            .iter()
+            .filter(|id| {
+                let item = created.iter().find(|item| &item.id == *id).unwrap();
+                item.title != "d" && item.title != "f"
+            })
+            .cloned()
+            .collect();
+        assert_eq!(page_ids(repo, NewsFilter::LiveAt(now)).await, live);
+    }
+
+    async fn sqlite() -> SqliteRepository {
+        SqliteRepository::open(":memory:").unwrap()
+    }
+
+    #[tokio::test]
+    async fn memory_issues_round_trip() {
+        issues_round_trip(&InMemoryRepository::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_issues_round_trip() {
+        issues_round_trip(&sqlite().await).await;
+    }
+
+    #[tokio::test]
+    async fn memory_news_round_trip() {
+        news_round_trip(&InMemoryRepository::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_news_round_trip() {
+        news_round_trip(&sqlite().await).await;
+    }
+
+    #[tokio::test]
+    async fn memory_news_publishing() {
+        news_publishing(&InMemoryRepository::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_news_publishing() {
+        news_publishing(&sqlite().await).await;
+    }
+
+    #[tokio::test]
+    async fn memory_news_pages() {
+        news_pages(&InMemoryRepository::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_news_pages() {
+        news_pages(&sqlite().await).await;
+    }
+
+    #[test]
+    fn cursors_round_trip_through_strings() {
+        let cursor = NewsCursor {
+            published_at: time(5, 0),
+            id: "abc.def".to_string(),
+        };
+        assert_eq!(cursor.to_string().parse::<NewsCursor>(), Ok(cursor));
+        for invalid in ["", "123", "123.", "x.abc", "99999999999999999999.abc"] {
+            assert!(
+                invalid.parse::<NewsCursor>().is_err(),
+                "{:?} parsed",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! A `MetadataRepository` backed by an embedded SQLite database.
+//!
+//! The schema is created on open, so pointing the server at a fresh path is enough to get a
+//! working offline database. Contributors are stored as a JSON array alongside each issue.
+//! News publish times are stored as RFC 3339 strings with millisecond precision, which sort in
+//! time order. Databases created by older versions gain the newer news columns on open.
+//! SQLite calls are blocking, so every query runs on tokio's blocking thread pool.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{
+    params, params_from_iter,
+    types::{Type, Value},
+    Connection, OptionalExtension,
+};
+
+use super::{
+    IssueUpdate, MetadataError, MetadataRepository, NewNewsItem, NewsCursor, NewsFilter, NewsPage,
+    NewsUpdate,
+};
+use crate::utils::{
+    dynamodb::{DBContributor, DBIssue},
+    news::{format_published_at, NewsItem},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS issues (
+    number INTEGER PRIMARY KEY,
+    blurb TEXT NOT NULL,
+    contributors TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS news (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    image_name TEXT NOT NULL,
+    published_at TEXT NOT NULL,
+    status TEXT NOT NULL,
+    publish_at TEXT
+);
+CREATE INDEX IF NOT EXISTS news_by_published_at ON news (published_at DESC, id DESC);
+";
+
+/// Brings the news table of an older database up to date, one step per missing column. Items
+/// that predate `published_at` are dated to the Unix epoch, so they stay visible and sort after
+/// everything created through the API; items that predate `status` are published and unscheduled.
+const NEWS_MIGRATIONS: [(&str, &str); 3] = [
+    (
+        "published_at",
+        "ALTER TABLE news ADD COLUMN published_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00.000Z';
+         CREATE INDEX IF NOT EXISTS news_by_published_at ON news (published_at DESC, id DESC);",
+    ),
+    (
+        "status",
+        "ALTER TABLE news ADD COLUMN status TEXT NOT NULL DEFAULT 'published';",
+    ),
+    ("publish_at", "ALTER TABLE news ADD COLUMN publish_at TEXT;"),
+];
+
+/// The columns `news_from_row` reads, in order.
+const NEWS_COLUMNS: &str = "id, title, description, image_name, status, published_at, publish_at";
+
+#[derive(Debug, Clone)]
+pub struct SqliteRepository {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteRepository {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MetadataError> {
+        let connection = Connection::open(path).map_err(anyhow::Error::from)?;
+        // An older news table would make the index in SCHEMA fail, so migrate it first
+        let has_news = connection
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'news'")
+            .and_then(|mut statement| statement.exists([]))
+            .map_err(anyhow::Error::from)?;
+        if has_news {
+            migrate_news(&connection)?;
+        }
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(anyhow::Error::from)?;
+        Ok(SqliteRepository {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` against the connection on the blocking thread pool.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, MetadataError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, MetadataError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection
+                .lock()
+                .map_err(|_| anyhow!("SQLite connection lock poisoned"))?;
+            f(&connection)
+        })
+        .await
+        .map_err(anyhow::Error::from)?
+    }
+}
+
+fn migrate_news(conn: &Connection) -> Result<(), MetadataError> {
+    let column_exists = |name: &str| {
+        conn.prepare("SELECT 1 FROM pragma_table_info('news') WHERE name = ?1")
+            .and_then(|mut statement| statement.exists(params![name]))
+            .map_err(anyhow::Error::from)
+    };
+    for (column, migration) in NEWS_MIGRATIONS {
+        if !column_exists(column)? {
+            conn.execute_batch(migration).map_err(anyhow::Error::from)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_issue(conn: &Connection, issue_number: usize) -> Result<DBIssue, MetadataError> {
+    let row = conn
+        .query_row(
+            "SELECT blurb, contributors FROM issues WHERE number = ?1",
+            params![issue_number as i64],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(anyhow::Error::from)?;
+    let (blurb, contributors) =
+        row.ok_or(MetadataError::NotFound(format!("issue {}", issue_number)))?;
+    let contributors: Vec<DBContributor> =
+        serde_json::from_str(&contributors).map_err(anyhow::Error::from)?;
+    Ok(DBIssue::new(issue_number, blurb, contributors))
+}
+
+fn write_issue(conn: &Connection, issue: &DBIssue) -> Result<(), MetadataError> {
+    let contributors = serde_json::to_string(&issue.contributors).map_err(anyhow::Error::from)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO issues (number, blurb, contributors) VALUES (?1, ?2, ?3)",
+        params![issue.number as i64, issue.blurb, contributors],
+    )
+    .map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+fn news_from_row(row: &rusqlite::Row) -> rusqlite::Result<NewsItem> {
+    Ok(NewsItem {
+        id: row.get::<_, i64>(0)?.to_string(),
+        title: row.get(1)?,
+        description: row.get(2)?,
+        image_name: row.get(3)?,
+        status: row.get::<_, String>(4)?.parse().map_err(|e: String| {
+            rusqlite::Error::FromSqlConversionFailure(4, Type::Text, e.into())
+        })?,
+        published_at: row
+            .get::<_, String>(5)?
+            .parse::<DateTime<Utc>>()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?,
+        publish_at: row
+            .get::<_, Option<String>>(6)?
+            .map(|publish_at| publish_at.parse::<DateTime<Utc>>())
+            .transpose()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e)))?,
+        image_url: None,
+    })
+}
+
+/// News ids are SQLite row ids; anything else cannot name an item.
+fn news_row_id(id: &str) -> Result<i64, MetadataError> {
+    id.parse::<i64>()
+        .map_err(|_| MetadataError::NotFound(format!("news item {}", id)))
+}
+
+fn read_news(conn: &Connection, id: i64) -> Result<NewsItem, MetadataError> {
+    conn.query_row(
+        &format!("SELECT {} FROM news WHERE id = ?1", NEWS_COLUMNS),
+        params![id],
+        news_from_row,
+    )
+    .optional()
+    .map_err(anyhow::Error::from)?
+    .ok_or(MetadataError::NotFound(format!("news item {}", id)))
+}
+
+#[async_trait]
+impl MetadataRepository for SqliteRepository {
+    async fn get_issue(&self, issue_number: usize) -> Result<DBIssue, MetadataError> {
+        self.with_connection(move |conn| read_issue(conn, issue_number))
+            .await
+    }
+
+    async fn list_issues(&self) -> Result<Vec<DBIssue>, MetadataError> {
+        self.with_connection(|conn| {
+            let mut statement = conn
+                .prepare("SELECT number, blurb, contributors FROM issues ORDER BY number")
+                .map_err(anyhow::Error::from)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })
+                .map_err(anyhow::Error::from)?;
+            rows.map(|row| {
+                let (number, blurb, contributors) = row.map_err(anyhow::Error::from)?;
+                let contributors: Vec<DBContributor> =
+                    serde_json::from_str(&contributors).map_err(anyhow::Error::from)?;
+                Ok(DBIssue::new(number as usize, blurb, contributors))
+            })
+            .collect()
+        })
+        .await
+    }
+
+    async fn put_issue(&self, issue: DBIssue) -> Result<(), MetadataError> {
+        self.with_connection(move |conn| write_issue(conn, &issue))
+            .await
+    }
+
+    async fn update_issue(
+        &self,
+        issue_number: usize,
+        update: IssueUpdate,
+    ) -> Result<DBIssue, MetadataError> {
+        // Both statements run under the same connection lock, so the update is atomic
+        self.with_connection(move |conn| {
+            let mut issue = read_issue(conn, issue_number)?;
+            update.apply(&mut issue);
+            write_issue(conn, &issue)?;
+            Ok(issue)
+        })
+        .await
+    }
+
+    async fn delete_issue(&self, issue_number: usize) -> Result<(), MetadataError> {
+        self.with_connection(move |conn| {
+            let deleted = conn
+                .execute(
+                    "DELETE FROM issues WHERE number = ?1",
+                    params![issue_number as i64],
+                )
+                .map_err(anyhow::Error::from)?;
+            if deleted == 0 {
+                return Err(MetadataError::NotFound(format!("issue {}", issue_number)));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_news(
+        &self,
+        limit: usize,
+        after: Option<NewsCursor>,
+        filter: NewsFilter,
+    ) -> Result<NewsPage, MetadataError> {
+        self.with_connection(move |conn| {
+            let mut conditions = Vec::new();
+            let mut values = Vec::new();
+            if let NewsFilter::LiveAt(now) = filter {
+                conditions.push("status = 'published' AND published_at <= ?");
+                values.push(Value::Text(format_published_at(now)));
+            }
+            if let Some(cursor) = &after {
+                conditions.push("(published_at, id) < (?, CAST(? AS INTEGER))");
+                values.push(Value::Text(format_published_at(cursor.published_at)));
+                values.push(Value::Text(cursor.id.clone()));
+            }
+            let filter = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", conditions.join(" AND "))
+            };
+            // One extra row tells us whether there is another page
+            values.push(Value::Integer(limit as i64 + 1));
+
+            let mut statement = conn
+                .prepare(&format!(
+                    "SELECT {} FROM news {} ORDER BY published_at DESC, id DESC LIMIT ?",
+                    NEWS_COLUMNS, filter
+                ))
+                .map_err(anyhow::Error::from)?;
+            let mut items = statement
+                .query_map(params_from_iter(values), news_from_row)
+                .map_err(anyhow::Error::from)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(anyhow::Error::from)?;
+            let has_more = items.len() > limit;
+            items.truncate(limit);
+            let next_cursor = if has_more {
+                items.last().map(NewsCursor::after)
+            } else {
+                None
+            };
+            Ok(NewsPage { items, next_cursor })
+        })
+        .await
+    }
+
+    async fn get_news(&self, id: &str) -> Result<NewsItem, MetadataError> {
+        let id = news_row_id(id)?;
+        self.with_connection(move |conn| read_news(conn, id)).await
+    }
+
+    async fn create_news(&self, item: NewNewsItem) -> Result<NewsItem, MetadataError> {
+        self.with_connection(move |conn| {
+            conn.execute(
+                "INSERT INTO news (title, description, image_name, status, published_at, \
+                 publish_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    item.title,
+                    item.description,
+                    item.image_name,
+                    item.status.as_str(),
+                    format_published_at(item.published_at),
+                    item.publish_at.map(format_published_at)
+                ],
+            )
+            .map_err(anyhow::Error::from)?;
+            read_news(conn, conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    async fn update_news(&self, id: &str, update: NewsUpdate) -> Result<NewsItem, MetadataError> {
+        let id = news_row_id(id)?;
+        self.with_connection(move |conn| {
+            let mut item = read_news(conn, id)?;
+            update.apply(&mut item);
+            conn.execute(
+                "UPDATE news SET title = ?1, description = ?2, image_name = ?3, status = ?4, \
+                 published_at = ?5, publish_at = ?6 WHERE id = ?7",
+                params![
+                    item.title,
+                    item.description,
+                    item.image_name,
+                    item.status.as_str(),
+                    format_published_at(item.published_at),
+                    item.publish_at.map(format_published_at),
+                    id
+                ],
+            )
+            .map_err(anyhow::Error::from)?;
+            // Read back what was stored, as `create_news` does, so times come back truncated
+            read_news(conn, id)
+        })
+        .await
+    }
+
+    async fn delete_news(&self, id: &str) -> Result<(), MetadataError> {
+        let row_id = news_row_id(id)?;
+        self.with_connection(move |conn| {
+            let deleted = conn
+                .execute("DELETE FROM news WHERE id = ?1", params![row_id])
+                .map_err(anyhow::Error::from)?;
+            if deleted == 0 {
+                return Err(MetadataError::NotFound(format!("news item {}", row_id)));
+            }
+            Ok(())
+        })
+        .await
+    }
+}
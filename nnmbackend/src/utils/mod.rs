@@ -1,5 +1,7 @@
-pub mod cognito;
 pub mod dynamodb;
+pub mod issues;
+pub mod metadata;
 pub mod news;
 pub mod s3;
-pub mod shopify;
\ No newline at end of file
+pub mod shopify;
+pub mod storage;
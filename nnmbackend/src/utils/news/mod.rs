@@ -1,81 +1,214 @@
-use std::time::Duration;
+use std::{fmt, str::FromStr, time::Duration};
 
-use aws_sdk_dynamodb::Client as DynamoClient;
-use aws_sdk_s3::{presigning::PresigningConfigBuilder, Client as S3Client};
-use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future::join_all;
 
-use super::s3::get_bucket_for_client;
+use super::{
+    metadata::{MetadataError, MetadataRepository, NewsCursor, NewsFilter, NewsPage},
+    storage::BlobStore,
+};
+
+/// The prefix news images uploaded through `POST /news` are stored under.
+pub const NEWS_IMAGE_PREFIX: &str = "nnm_news/";
+
+/// Whether a news item may appear in the public feed. A published item still waits for its
+/// `published_at` time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewsStatus {
+    Draft,
+    Published,
+}
+
+impl NewsStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NewsStatus::Draft => "draft",
+            NewsStatus::Published => "published",
+        }
+    }
+}
+
+impl fmt::Display for NewsStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NewsStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(NewsStatus::Draft),
+            "published" => Ok(NewsStatus::Published),
+            other => Err(format!(
+                "unknown news status {:?}, expected draft or published",
+                other
+            )),
+        }
+    }
+}
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct NewsItem {
+    pub id: String,
     pub title: String,
     pub description: String,
     pub image_name: String,
-    pub image_url: Option<String>
+    pub status: NewsStatus,
+    /// When the item is (or will be) published. Also the order of the feed.
+    pub published_at: DateTime<Utc>,
+    /// The time the item was scheduled for, if an editor chose one. `published_at` is the same
+    /// time for scheduled items.
+    pub publish_at: Option<DateTime<Utc>>,
+    pub image_url: Option<String>,
 }
 
-pub async fn get_latest_news(dbclient: &DynamoClient, s3client: &S3Client) -> Result<Vec<NewsItem>, anyhow::Error> {
-    let bucket = get_bucket_for_client(s3client);
-    // Only get the latest 5 news items
-    let response = dbclient
-        .scan()
-        .table_name("nnmNews")
-        .filter_expression("attribute_exists(title)")
-        .projection_expression("title, description, image_name")
-        .limit(5)
-        .send()
-        .await?;
-
-    let items = response.items.ok_or(anyhow!("Could not retrieve items"))?;
-    let mut news_item_results: Vec<Result<NewsItem, anyhow::Error>> = items
-        .iter()
-        .map(|item| {
-            let title = item
-                .get("title")
-                .ok_or(anyhow!("Title not found"))?
-                .as_s().unwrap();
-            let description = item
-                .get("description")
-                .ok_or(anyhow!("Description not found"))?
-                .as_s().unwrap();
-            let image_name = item
-                .get("image_name")
-                .ok_or(anyhow!("Image name not found"))?
-                .as_s().unwrap();
-
-            Ok::<NewsItem, anyhow::Error>(NewsItem {
+impl NewsItem {
+    /// Whether the item belongs in the public feed at `now`.
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.status == NewsStatus::Published && self.published_at <= now
+    }
+}
+
+/// Formats a publish time the way it is stored: RFC 3339 in UTC with millisecond precision,
+/// so stored timestamps sort correctly as strings.
+pub fn format_published_at(published_at: DateTime<Utc>) -> String {
+    published_at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// A fresh, unique key for a news image with the given file extension.
+pub fn news_image_key(extension: &str) -> String {
+    format!(
+        "{}{}.{}",
+        NEWS_IMAGE_PREFIX,
+        hex::encode(rand::random::<[u8; 16]>()),
+        extension
+    )
+}
+
+/// Identifies an image from its first bytes, returning the file extension to store it under.
+pub fn sniff_image_extension(header: &[u8]) -> Option<&'static str> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Fills in `image_url` for a single item. If the URL cannot be signed the item is returned
+/// without one and a warning is logged, so one bad image does not hide the rest of the news.
+pub async fn sign_news_image(
+    mut item: NewsItem,
+    store: &dyn BlobStore,
+    presign_ttl: Duration,
+) -> NewsItem {
+    match store.signed_url(&item.image_name, presign_ttl).await {
+        Ok(url) => item.image_url = Some(url),
+        Err(e) => log::warn!(
+            "Could not sign image {} for news item {}: {}",
+            item.image_name,
+            item.id,
+            e
+        ),
+    }
+    item
+}
+
+pub async fn get_latest_news(
+    repo: &dyn MetadataRepository,
+    store: &dyn BlobStore,
+    limit: usize,
+    after: Option<NewsCursor>,
+    filter: NewsFilter,
+    presign_ttl: Duration,
+) -> Result<NewsPage, MetadataError> {
+    let page = repo.list_news(limit, after, filter).await?;
+
+    // Presign every image at once; join_all keeps the feed order
+    let items = join_all(
+        page.items
+            .into_iter()
+            .map(|item| sign_news_image(item, store, presign_ttl)),
+    )
+    .await;
+
+    Ok(NewsPage { items, ..page })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{local_store, Faults, FaultyStore},
+        utils::metadata::{memory::InMemoryRepository, NewNewsItem},
+    };
+
+    async fn feed(faults: Faults) -> NewsPage {
+        let root = tempfile::tempdir().unwrap();
+        let store = FaultyStore::new(local_store(root.path()), faults);
+        let repo = InMemoryRepository::new();
+        let now = Utc::now();
+        for (title, age) in [("first", 2), ("second", 1)] {
+            let item = NewNewsItem {
                 title: title.to_string(),
-                description: description.to_string(),
-                image_name: image_name.to_string(),
-                image_url: None
-            })
-        })
-        .collect();
-
-    let mut news_items = Vec::new();
-
-    for news_item in news_item_results.iter_mut() {
-        if let Err(e) = news_item {
-            return Err(anyhow!("Error: {}", e));
-        } 
-        let news_item = news_item.as_mut().unwrap();
-        // Get a presigned url
-        let conf = PresigningConfigBuilder::default()
-        .expires_in(Duration::from_secs(30))
-        .build()
-        .unwrap();
-        let ret = s3client
-            .get_object()
-            .bucket(bucket)
-            .key(news_item.image_name.clone())
-            .presigned(conf)
-            .await?;
-        
-        let image_url = ret.uri().to_string();
-
-        news_item.image_url = Some(image_url);
-        news_items.push(news_item.clone());
+                description: "Details".to_string(),
+                image_name: format!("{}{}.png", NEWS_IMAGE_PREFIX, title),
+                status: NewsStatus::Published,
+                published_at: now - chrono::Duration::minutes(age),
+                publish_at: None,
+            };
+            repo.create_news(item).await.unwrap();
+        }
+        get_latest_news(
+            &repo,
+            &store,
+            10,
+            None,
+            NewsFilter::LiveAt(Utc::now()),
+            Duration::from_secs(60),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signs_every_image_in_feed_order() {
+        let page = feed(Faults::default()).await;
+        let titles: Vec<_> = page.items.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, ["second", "first"]);
+        for item in &page.items {
+            let url = item.image_url.as_deref().unwrap();
+            assert!(url.starts_with(&format!("http://localhost/blob/{}?", item.image_name)));
+        }
     }
 
-    Ok(news_items)
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn an_image_that_cannot_be_signed_has_no_url() {
+        let faults = Faults {
+            sign: true,
+            ..Faults::default()
+        };
+        let page = feed(faults).await;
+        assert_eq!(page.items.len(), 2);
+        assert!(page.items.iter().all(|item| item.image_url.is_none()));
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn sniffs_supported_image_types() {
+        assert_eq!(sniff_image_extension(b"\xFF\xD8\xFF\xE0"), Some("jpg"));
+        assert_eq!(sniff_image_extension(b"\x89PNG\r\n\x1a\n"), Some("png"));
+        assert_eq!(sniff_image_extension(b"GIF89a"), Some("gif"));
+        assert_eq!(sniff_image_extension(b"RIFF\0\0\0\0WEBP"), Some("webp"));
+        assert_eq!(sniff_image_extension(b"%PDF-1.5"), None);
+        assert_eq!(sniff_image_extension(b"RIFF"), None);
+    }
+}
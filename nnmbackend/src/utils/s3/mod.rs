@@ -1,166 +1,314 @@
-/// This module provides utility functions for interacting with AWS S3.
-///
-/// The functions include creating an S3 client, retrieving the latest issue count,
-/// generating signed URLs for accessing issues, and determining the appropriate S3 bucket
-/// based on the client's region.
-///
-/// # Functions
-///
-/// - `get_s3_client`: Creates and returns an S3 client configured with the appropriate region.
-/// - `get_issue_count`: Retrieves the count of issues available in the S3 bucket.
-/// - `get_bucket_for_client`: Determines the appropriate S3 bucket based on the client's region.
-/// - `get_signed_url_for_latest_issue`: Generates a signed URL for the latest issue available in the S3 bucket.
-/// - `get_signed_url_for_issue`: Generates a signed URL for a specific issue based on the issue number.
-///
-/// # Example
-///
-/// ```
-/// use nnmbackend::utils::s3::{get_s3_client, get_signed_url_for_latest_issue};
-///
-/// #[tokio::main]
-/// async fn main() {
-///     let s3client = get_s3_client().await;
-///     match get_signed_url_for_latest_issue(&s3client).await {
-///         Ok(url) => println!("Signed URL: {}", url),
-///         Err(e) => eprintln!("Error generating signed URL: {}", e),
-///     }
-/// }
-/// ```
-///
-/// # Errors
-///
-/// These functions return various errors related to S3 operations, such as `S3Error::NotFound`
-/// if the specified bucket or object is not found, or other errors related to AWS SDK operations.
-
-use std::time::Duration;
-
-use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
-use aws_sdk_s3::Client as S3Client;
-use aws_sdk_s3::{presigning::PresigningConfigBuilder, types::error::NotFound, Error as S3Error};
-
-pub async fn get_s3_client() -> S3Client {
-    let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .region(region_provider)
-        .load()
-        .await;
-    S3Client::new(&config)
+//! This module provides utility functions for interacting with AWS S3.
+//!
+//! The functions include determining the appropriate S3 bucket based on the client's region.
+//! `S3BlobStore` adapts the client to the `BlobStore` trait used by the routes. Files larger
+//! than one part are streamed with a multipart upload, one part in memory at a time; failed
+//! parts are retried, and the multipart upload is aborted if a part keeps failing.
+//!
+//! # Functions
+//!
+//! - `get_bucket_for_client`: Looks up the configured S3 bucket for the client's region.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::s3::{get_bucket_for_client, S3BlobStore};
+//! use nnmbackend::utils::storage::{get_issue_index, get_signed_url_for_latest_issue};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let client = aws_sdk_s3::Client::new(&aws_config::load_from_env().await);
+//!     let buckets = [("us-east-1".to_string(), "nonothingissues1".to_string())].into();
+//!     let bucket = get_bucket_for_client(&client, &buckets).unwrap().to_string();
+//!     let store = S3BlobStore::new(client, bucket, 8 * 1024 * 1024);
+//!     let ttl = std::time::Duration::from_secs(30);
+//!     let index = get_issue_index(&store).await.unwrap();
+//!     match get_signed_url_for_latest_issue(&index, &store, ttl).await {
+//!         Ok(url) => println!("Signed URL: {}", url),
+//!         Err(e) => eprintln!("Error generating signed URL: {}", e),
+//!     }
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! `S3BlobStore` maps missing keys to `StorageError::NotFound` and every other AWS SDK
+//! failure to `StorageError::Backend`.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    operation::get_object::GetObjectError,
+    presigning::PresigningConfigBuilder,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
+    Client as S3Client,
+};
+use chrono::DateTime;
+use tokio::io::AsyncReadExt;
+
+use super::storage::{BlobInfo, BlobStore, StorageError};
+
+/// Returns the bucket configured for the client's region, if there is one.
+pub fn get_bucket_for_client<'a>(
+    s3client: &S3Client,
+    buckets: &'a BTreeMap<String, String>,
+) -> Option<&'a str> {
+    let region = s3client.config().region()?;
+    buckets.get(region.as_ref()).map(String::as_str)
 }
 
-pub async fn get_issue_count(s3client: &S3Client) -> Result<usize, S3Error> {
-    let bucket = get_bucket_for_client(s3client);
-
-    let Ok(issues) = s3client
-        .list_objects_v2()
-        .bucket(bucket)
-        .send()
-        .await
-    else {
-        return Err(S3Error::NotFound(
-            NotFound::builder()
-                .message("Issue with S3Client or bucket")
-                .build(),
-        ));
-    };
-
-    let latest_issue = issues.contents()
-        .iter()
-        .map(|obj| {
-            let key = obj.key.as_ref().unwrap();
-            // Key may include prefix before issue_, so we trim that off
-            // Trim up to end of "issue_":
-            let key = key.find("issue_").map(|idx| &key[idx..]).unwrap_or(key);
-            // check if key is a pdf
-            if !key.ends_with(".pdf") {
-                return 0;
-            }
-            println!("{}", key);
-            let issue_number = key
-                .trim_start_matches("issue_")
-                .trim_end_matches(".pdf")
-                .parse::<usize>()
-                .unwrap();
-
-            issue_number
-        })
-        .max()
-        .unwrap();
-
-    return Ok(latest_issue);
+/// How many times a single part is attempted before the multipart upload is aborted.
+const MAX_PART_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+    client: S3Client,
+    bucket: String,
+    part_size: usize,
 }
 
-pub fn get_bucket_for_client(s3client: &S3Client) -> &'static str {
-    if s3client
-        .config()
-        .region()
-        .is_some_and(|reg| reg.as_ref() == "us-east-1")
-    {
-        "nonothingissues1"
-    } else if s3client
-        .config()
-        .region()
-        .is_some_and(|reg| reg.as_ref() == "us-east-2")
-    {
-        "nonothingissues"
-    } else {
-        panic!("Invalid region");
+impl S3BlobStore {
+    pub fn new(client: S3Client, bucket: String, part_size: usize) -> Self {
+        S3BlobStore {
+            client,
+            bucket,
+            part_size,
+        }
     }
-}
 
-pub async fn get_signed_url_for_latest_issue(s3client: &S3Client) -> Result<String, S3Error> {
-    // Need to list all issues to check for latest, since some may be missing
-    let bucket = get_bucket_for_client(s3client);
-    
-    let objects = s3client
-        .list_objects_v2()
-        .bucket(bucket)
-        .send()
-        .await?
-        .contents
-        .unwrap_or_default();
-    let latest_issue = objects
-        .iter()
-        .map(|obj| {
-            let key = obj.key.as_ref().unwrap();
-            // Key may include prefix before issue_, so we trim that off
-            // Trim up to end of "issue_":
-            let key = key.find("issue_").map(|idx| &key[idx..]).unwrap_or(key);
-            // check if key is a pdf
-            if !key.ends_with(".pdf") {
-                return 0;
+    /// Uploads one part, retrying with exponential backoff.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        bytes: Vec<u8>,
+    ) -> Result<CompletedPart, StorageError> {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(ByteStream::from(bytes.clone()))
+                .send()
+                .await;
+            match result {
+                Ok(output) => {
+                    return Ok(CompletedPart::builder()
+                        .set_e_tag(output.e_tag)
+                        .part_number(part_number)
+                        .build())
+                }
+                Err(e) if attempt < MAX_PART_ATTEMPTS => {
+                    log::warn!(
+                        "Part {} of {} failed (attempt {}), retrying: {}",
+                        part_number,
+                        key,
+                        attempt,
+                        e
+                    );
+                    tokio::time::sleep(Duration::from_millis(200 << attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(anyhow!("Part {} of {} failed: {}", part_number, key, e).into())
+                }
             }
-            
-            let issue_number = key
-                .trim_start_matches("issue_")
-                .trim_end_matches(".pdf")
-                .parse::<usize>()
-                .unwrap();
-
-            issue_number
-        })
-        .max()
-        .unwrap();
-
-    get_signed_url_for_issue(latest_issue, s3client).await
+        }
+    }
+
+    /// Reads `path` one part at a time and uploads each part under `upload_id`.
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        path: &Path,
+    ) -> Result<Vec<CompletedPart>, StorageError> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(anyhow::Error::from)?;
+        let mut parts = Vec::new();
+        loop {
+            let mut buffer = Vec::with_capacity(self.part_size);
+            (&mut file)
+                .take(self.part_size as u64)
+                .read_to_end(&mut buffer)
+                .await
+                .map_err(anyhow::Error::from)?;
+            if buffer.is_empty() {
+                break;
+            }
+            let part_number = parts.len() as i32 + 1;
+            parts.push(
+                self.upload_part(key, upload_id, part_number, buffer)
+                    .await?,
+            );
+        }
+        Ok(parts)
+    }
 }
 
-pub async fn get_signed_url_for_issue(
-    issue_number: usize,
-    s3client: &S3Client,
-) -> Result<String, S3Error> {
-    let issue_key = format!("nnm_issues/issue_{}.pdf", issue_number);
-    // Generate a signed URL for the issue
-    let bucket = get_bucket_for_client(s3client);
-    
-    let conf = PresigningConfigBuilder::default()
-        .expires_in(Duration::from_secs(30))
-        .build()
-        .unwrap();
-    let ret = s3client
-        .get_object()
-        .bucket(bucket)
-        .key(issue_key)
-        .presigned(conf)
-        .await?;
-    Ok(ret.uri().to_string())
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>, StorageError> {
+        // A single response holds at most 1000 keys, so follow the continuation tokens
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+
+        let mut objects = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| anyhow!("Issue with S3Client or bucket: {}", e))?;
+            objects.extend(page.contents().iter().filter_map(|obj| {
+                Some(BlobInfo {
+                    key: obj.key()?.to_string(),
+                    size: obj.size().unwrap_or_default().max(0) as u64,
+                    last_modified: obj
+                        .last_modified()
+                        .and_then(|t| DateTime::from_timestamp(t.secs(), t.subsec_nanos())),
+                })
+            }));
+        }
+        Ok(objects)
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), StorageError> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .map_err(anyhow::Error::from)?
+            .len();
+        if size <= self.part_size as u64 {
+            let body = ByteStream::from_path(path)
+                .await
+                .map_err(|e| anyhow!("{}", e))?;
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| anyhow!("{}", e))?;
+            return Ok(());
+        }
+
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        let upload_id = upload
+            .upload_id()
+            .ok_or_else(|| anyhow!("S3 did not return an upload id for {}", key))?;
+
+        let completed = match self.upload_parts(key, upload_id, path).await {
+            Ok(parts) => self
+                .client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(|e| anyhow!("{}", e).into()),
+            Err(e) => Err(e),
+        };
+
+        if completed.is_err() {
+            // Otherwise the uploaded parts linger (and are billed) until a lifecycle rule removes them
+            if let Err(e) = self
+                .client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                log::error!("Could not abort multipart upload of {}: {}", key, e);
+            }
+        }
+        completed
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        // A single CopyObject handles objects up to 5 GiB, well above the upload limit
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, from))
+            .key(to)
+            .send()
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        // S3 reports success for keys that do not exist
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| match e.into_service_error() {
+                GetObjectError::NoSuchKey(_) => StorageError::NotFound(key.to_string()),
+                other => StorageError::Backend(other.into()),
+            })?;
+        let bytes = response
+            .body
+            .collect()
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(bytes.into_bytes().to_vec())
+    }
+
+    async fn signed_url(&self, key: &str, ttl: Duration) -> Result<String, StorageError> {
+        let conf = PresigningConfigBuilder::default()
+            .expires_in(ttl)
+            .build()
+            .map_err(anyhow::Error::from)?;
+        let ret = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(conf)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(ret.uri().to_string())
+    }
 }
@@ -1,38 +1,42 @@
-/// This module provides utility functions for creating Shopify carts.
-///
-/// The utilities leverage `std::collections::HashMap` to manage cart data.
-///
-/// # Functions
-///
-/// - `create_cart`: Initializes a new cart with given items and quantities.
-///
-/// # Example
-///
-/// ```
-/// use std::collections::HashMap;
-/// use nnmbackend::utils::shopify::cart::create_cart;
-///
-/// fn main() {
-///     let mut items = HashMap::new();
-///     items.insert("item1".to_string(), 2);
-///     items.insert("item2".to_string(), 1);
-///
-///     let cart = create_cart(items);
-///     println!("{:?}", cart);
-/// }
-/// ```
-///
-/// # Errors
-///
-/// The `create_cart` function may return an error if the input data is invalid or if there is an issue initializing the cart.
-
-use std::collections::HashMap;
-
-use crate::utils::shopify::graphql::{
-    actions::{GraphQLAction, GraphQLQuery},
-    api::{CartAPIRepresentation, UserError},
+//! This module provides utility functions for creating Shopify carts.
+//!
+//! # Functions
+//!
+//! - `create_cart_mutation`: Constructs a `cartCreate` mutation, optionally with the cart's initial lines.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::shopify::{create_cart_mutation, payloads::MultiItemPayload};
+//!
+//! // An empty cart
+//! let mutation = create_cart_mutation(None);
+//!
+//! // A cart created with its lines in the same call
+//! let items = MultiItemPayload {
+//!     items: vec![
+//!         // Add items here
+//!     ],
+//! };
+//! let mutation = create_cart_mutation(Some(&items));
+//! // Execute the mutation with a `ShopifyClient`
+//! ```
+//!
+//! # Errors
+//!
+//! Shopify reports unknown merchandise ids and invalid quantities in the mutation's `userErrors`.
+
+use crate::utils::shopify::{
+    graphql::{
+        actions::GraphQLQuery,
+        api::{CartAPIRepresentation, UserError},
+        types::ShopifyGraphQLType,
+    },
+    payloads::MultiItemPayload,
 };
 
+use super::add_item::create_shopify_line_entry;
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct CartCreateAPIResponse {
     pub cart: CartAPIRepresentation,
@@ -46,15 +50,97 @@ pub struct CartCreateResponse {
     pub cart_create: CartCreateAPIResponse,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
-pub struct FullCartCreateResponse {
-    pub data: CartCreateResponse,
+/// Builds a `cartCreate` mutation. Any `items` are passed as the `lines` of its `CartInput`, so
+/// the cart is created with them in one call; with no items the cart starts empty.
+pub fn create_cart_mutation(
+    items: Option<&MultiItemPayload>,
+) -> GraphQLQuery<CartAPIRepresentation> {
+    let mut mutation = GraphQLQuery::mutation(
+        CartAPIRepresentation::default(),
+        Some("cartCreate".to_string()),
+    );
+    let Some(items) = items.filter(|items| !items.items.is_empty()) else {
+        return mutation;
+    };
+    mutation.add_variable(
+        "input".to_string(),
+        ShopifyGraphQLType::Custom(
+            "CartInput".to_string(),
+            ShopifyGraphQLType::Object(
+                vec![(
+                    "lines".to_string(),
+                    ShopifyGraphQLType::Array(
+                        items
+                            .items
+                            .iter()
+                            .map(|item| create_shopify_line_entry(&item.product_id, item.quantity))
+                            .collect(),
+                    ),
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .into(),
+        ),
+    );
+
+    mutation
 }
 
-pub fn create_cart_mutation() -> GraphQLQuery<CartAPIRepresentation> {
-    GraphQLQuery::new(
-        GraphQLAction::Mutation(Some("cartCreate".to_string())),
-        CartAPIRepresentation::default(),
-        HashMap::new(),
-    )
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::utils::shopify::payloads::ItemPayload;
+
+    fn item(product_id: &str, quantity: u32) -> ItemPayload {
+        ItemPayload {
+            product_id: product_id.to_string(),
+            title: String::new(),
+            handle: String::new(),
+            description: String::new(),
+            price: 0.0,
+            currency: String::new(),
+            quantity,
+        }
+    }
+
+    fn payload(items: Option<&MultiItemPayload>) -> Value {
+        let payload = create_cart_mutation(items).to_payload().unwrap();
+        serde_json::from_str(&payload).expect("payload is not valid JSON")
+    }
+
+    #[test]
+    fn initial_lines_reach_the_cart_input() {
+        let items = MultiItemPayload {
+            items: vec![
+                item("gid://shopify/ProductVariant/1", 2),
+                item("gid://shopify/ProductVariant/2", 1),
+            ],
+        };
+        let payload = payload(Some(&items));
+
+        let text = payload["query"].as_str().unwrap();
+        assert!(text.starts_with("mutation"));
+        assert!(text.contains("cartCreate"));
+        assert!(text.contains("$input: CartInput"));
+        assert_eq!(
+            payload["variables"]["input"]["lines"],
+            json!([
+                { "merchandiseId": "gid://shopify/ProductVariant/1", "quantity": 2 },
+                { "merchandiseId": "gid://shopify/ProductVariant/2", "quantity": 1 },
+            ])
+        );
+    }
+
+    #[test]
+    fn a_cart_without_items_declares_no_input() {
+        let empty = MultiItemPayload { items: vec![] };
+        for items in [None, Some(&empty)] {
+            let payload = payload(items);
+            assert!(payload["query"].as_str().unwrap().contains("cartCreate"));
+            assert!(payload["variables"].get("input").is_none());
+        }
+    }
 }
@@ -1,3 +1,5 @@
 pub mod add_item;
 pub mod create_cart;
 pub mod get_cart;
+pub mod remove_lines;
+pub mod update_lines;
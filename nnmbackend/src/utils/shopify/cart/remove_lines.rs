@@ -0,0 +1,75 @@
+//! This module provides functionality for removing lines from a Shopify cart using GraphQL.
+//!
+//! # Structs
+//!
+//! - `RemoveLinesAPIResponse`: Represents the result of the `cartLinesRemove` mutation.
+//! - `RemoveLinesResponse`: Represents the `data` of the response, holding the `cartLinesRemove` result.
+//!
+//! # Functions
+//!
+//! - `remove_lines_mutation`: Constructs a GraphQL mutation removing the given cart lines.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::shopify::{payloads::LineRemovalPayload, remove_lines_mutation};
+//!
+//! let payload = LineRemovalPayload {
+//!     line_ids: vec!["gid://shopify/CartLine/1".to_string()],
+//! };
+//!
+//! let mutation = remove_lines_mutation("example_cart_id", &payload);
+//! // Execute the mutation with a `ShopifyClient`
+//! ```
+//!
+//! # Errors
+//!
+//! Shopify reports unknown line ids in the mutation's `userErrors`.
+
+use crate::utils::shopify::{
+    graphql::{
+        actions::GraphQLQuery,
+        api::{CartAPIRepresentation, UserError},
+        types::ShopifyGraphQLType,
+    },
+    payloads::LineRemovalPayload,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RemoveLinesAPIResponse {
+    pub cart: Option<CartAPIRepresentation>,
+    #[serde(rename = "userErrors")]
+    pub user_errors: Option<Vec<UserError>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RemoveLinesResponse {
+    #[serde(rename = "cartLinesRemove")]
+    pub remove_lines: Option<RemoveLinesAPIResponse>,
+}
+
+pub fn remove_lines_mutation(
+    cart_id: &str,
+    lines: &LineRemovalPayload,
+) -> GraphQLQuery<CartAPIRepresentation> {
+    let mut mutation = GraphQLQuery::mutation(
+        CartAPIRepresentation::default(),
+        Some("cartLinesRemove".to_string()),
+    );
+    mutation.add_variable(
+        "cartId".to_string(),
+        ShopifyGraphQLType::ID(format!("gid://shopify/Cart/{}", cart_id)),
+    );
+    mutation.add_variable(
+        "lineIds".to_string(),
+        ShopifyGraphQLType::Array(
+            lines
+                .line_ids
+                .iter()
+                .map(|line_id| ShopifyGraphQLType::ID(line_id.clone()))
+                .collect(),
+        ),
+    );
+
+    mutation
+}
@@ -0,0 +1,104 @@
+//! This module provides functionality for changing the quantities of lines already in a Shopify cart
+//! using GraphQL.
+//!
+//! # Structs
+//!
+//! - `UpdateLinesAPIResponse`: Represents the result of the `cartLinesUpdate` mutation.
+//! - `UpdateLinesResponse`: Represents the `data` of the response, holding the `cartLinesUpdate` result.
+//!
+//! # Functions
+//!
+//! - `update_lines_mutation`: Constructs a GraphQL mutation setting the quantity of each given cart line.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::shopify::{
+//!     payloads::{LineUpdatePayload, MultiLineUpdatePayload},
+//!     update_lines_mutation,
+//! };
+//!
+//! let payload = MultiLineUpdatePayload {
+//!     items: vec![LineUpdatePayload {
+//!         line_id: "gid://shopify/CartLine/1".to_string(),
+//!         quantity: 3,
+//!     }],
+//! };
+//!
+//! let mutation = update_lines_mutation("example_cart_id", &payload);
+//! // Execute the mutation with a `ShopifyClient`
+//! ```
+//!
+//! # Errors
+//!
+//! Shopify reports unknown line ids and invalid quantities in the mutation's `userErrors`. Setting a
+//! line's quantity to 0 removes it from the cart.
+
+use crate::utils::shopify::{
+    graphql::{
+        actions::GraphQLQuery,
+        api::{CartAPIRepresentation, UserError},
+        types::ShopifyGraphQLType,
+    },
+    payloads::MultiLineUpdatePayload,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct UpdateLinesAPIResponse {
+    pub cart: Option<CartAPIRepresentation>,
+    #[serde(rename = "userErrors")]
+    pub user_errors: Option<Vec<UserError>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct UpdateLinesResponse {
+    #[serde(rename = "cartLinesUpdate")]
+    pub update_lines: Option<UpdateLinesAPIResponse>,
+}
+
+fn create_shopify_line_update(line_id: &str, quantity: u32) -> ShopifyGraphQLType {
+    ShopifyGraphQLType::Custom(
+        "CartLineUpdateInput".to_string(),
+        ShopifyGraphQLType::Object(
+            vec![
+                (
+                    "id".to_string(),
+                    ShopifyGraphQLType::ID(line_id.to_string()),
+                ),
+                (
+                    "quantity".to_string(),
+                    ShopifyGraphQLType::Int(quantity as i64),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .into(),
+    )
+}
+
+pub fn update_lines_mutation(
+    cart_id: &str,
+    items: &MultiLineUpdatePayload,
+) -> GraphQLQuery<CartAPIRepresentation> {
+    let mut mutation = GraphQLQuery::mutation(
+        CartAPIRepresentation::default(),
+        Some("cartLinesUpdate".to_string()),
+    );
+    mutation.add_variable(
+        "cartId".to_string(),
+        ShopifyGraphQLType::ID(format!("gid://shopify/Cart/{}", cart_id)),
+    );
+    mutation.add_variable(
+        "lines".to_string(),
+        ShopifyGraphQLType::Array(
+            items
+                .items
+                .iter()
+                .map(|item| create_shopify_line_update(&item.line_id, item.quantity))
+                .collect(),
+        ),
+    );
+
+    mutation
+}
@@ -0,0 +1,432 @@
+//! A typed client for the Shopify Storefront API.
+//!
+//! `ShopifyClient` owns one pooled `reqwest::Client`, configured with the request timeout from
+//! `shopify.timeout_secs`, and is shared by every route through `AppState`. `execute` sends a
+//! `GraphQLQuery`, retries it when Shopify is throttling or failing, and returns the response's
+//! `data` deserialized into the caller's type.
+//!
+//! # Retries
+//!
+//! A request is tried up to `shopify.max_attempts` times. Every request is retried when the
+//! connection could not be made, when Shopify answers with HTTP 429, and when the GraphQL
+//! response reports a `THROTTLED` error, since Shopify did not run it in any of those cases.
+//! Queries are also retried when they time out or Shopify answers with a 5xx status. Mutations
+//! are not: Shopify may have applied one before failing, and sending a `cartCreate` or
+//! `cartLinesAdd` again would create a second cart or duplicate lines. The wait before the
+//! next try is, in order of preference:
+//!
+//! 1. the time the query cost needs to be restored, computed from the `extensions.cost`
+//!    throttle information Shopify attaches to throttled responses;
+//! 2. the `Retry-After` header;
+//! 3. an exponential backoff starting at 200ms.
+//!
+//! No wait is longer than 10 seconds.
+//!
+//! # Errors
+//!
+//! `execute` returns a `ShopifyError`. A query whose variables cannot be encoded fails with
+//! `ShopifyError::Encode` before anything is sent. Top-level GraphQL `errors` become
+//! `ShopifyError::GraphQL`, and a non-empty `userErrors` list on any mutation in the response
+//! becomes `ShopifyError::UserErrors`, so callers never need to check either themselves.
+
+use std::time::Duration;
+
+use reqwest::{
+    header::{self, HeaderMap},
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::config::ShopifyConfig;
+
+use super::graphql::{
+    actions::{GraphQLAction, GraphQLQuery},
+    api::{GraphQLError, UserError},
+    traits::GraphQLRepresentable,
+};
+
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(200);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShopifyError {
+    #[error("Error contacting Shopify: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Shopify answered with HTTP {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("Shopify is throttling requests")]
+    Throttled,
+    #[error(
+        "Shopify returned errors: {}",
+        .0.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("; ")
+    )]
+    GraphQL(Vec<GraphQLError>),
+    #[error(
+        "Shopify rejected the request: {}",
+        .0.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("; ")
+    )]
+    UserErrors(Vec<UserError>),
+    #[error("Unexpected response from Shopify: {0}")]
+    Decode(String),
+    #[error("Could not encode the request to Shopify: {0}")]
+    Encode(String),
+}
+
+/// The envelope of every GraphQL response.
+#[derive(Debug, serde::Deserialize)]
+struct GraphQLResponse {
+    data: Option<Value>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+    extensions: Option<Value>,
+}
+
+/// Whether and when a failed attempt should be tried again.
+enum Retry {
+    No,
+    Backoff,
+    After(Duration),
+}
+
+#[derive(Clone)]
+pub struct ShopifyClient {
+    http: reqwest::Client,
+    url: String,
+    storefront_key: String,
+    api_version: String,
+    max_attempts: u32,
+}
+
+impl ShopifyClient {
+    pub fn new(config: &ShopifyConfig) -> Result<Self, ShopifyError> {
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout())
+            .connect_timeout(CONNECT_TIMEOUT.min(config.timeout()))
+            .build()?;
+        Ok(ShopifyClient {
+            http,
+            url: config.graphql_url(),
+            storefront_key: config.storefront_key.clone(),
+            api_version: config.api_version.clone(),
+            max_attempts: config.max_attempts,
+        })
+    }
+
+    /// Sends `query` and deserializes the response's `data` as `T`.
+    pub async fn execute<T, Q>(&self, query: &GraphQLQuery<Q>) -> Result<T, ShopifyError>
+    where
+        T: DeserializeOwned,
+        Q: GraphQLRepresentable,
+    {
+        let payload = query
+            .to_payload()
+            .map_err(|e| ShopifyError::Encode(e.to_string()))?;
+        let idempotent = query.action == GraphQLAction::Query;
+        let mut attempt = 1;
+        let response = loop {
+            let (error, retry) = match self.attempt(&payload, idempotent).await {
+                Ok(response) => break response,
+                Err(failure) => failure,
+            };
+            let delay = match retry {
+                Retry::No => return Err(error),
+                _ if attempt >= self.max_attempts => return Err(error),
+                Retry::Backoff => backoff_delay(attempt),
+                Retry::After(delay) => delay,
+            }
+            .min(MAX_RETRY_DELAY);
+            log::warn!(
+                "Shopify request failed (attempt {} of {}), retrying in {:?}: {}",
+                attempt,
+                self.max_attempts,
+                delay,
+                error
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let data = response
+            .data
+            .ok_or_else(|| ShopifyError::Decode("response has no data".to_string()))?;
+        let user_errors = collect_user_errors(&data)?;
+        if !user_errors.is_empty() {
+            return Err(ShopifyError::UserErrors(user_errors));
+        }
+        serde_json::from_value(data).map_err(|e| ShopifyError::Decode(e.to_string()))
+    }
+
+    /// Sends the request once, classifying any failure as retryable or not. Failures after
+    /// which Shopify may have run the request are only retryable when it is `idempotent`.
+    async fn attempt(
+        &self,
+        payload: &str,
+        idempotent: bool,
+    ) -> Result<GraphQLResponse, (ShopifyError, Retry)> {
+        let response = self
+            .http
+            .post(&self.url)
+            .header("X-Shopify-Storefront-Access-Token", &self.storefront_key)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/json")
+            .header("X-Shopify-Api-Version", &self.api_version)
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|e| {
+                // A failed connect never reached Shopify; a timeout may have
+                let retry = if e.is_connect() || (idempotent && e.is_timeout()) {
+                    Retry::Backoff
+                } else {
+                    Retry::No
+                };
+                (ShopifyError::Http(e), retry)
+            })?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || (idempotent && status.is_server_error()) {
+            let retry = retry_after(response.headers()).map_or(Retry::Backoff, Retry::After);
+            let error = if status == StatusCode::TOO_MANY_REQUESTS {
+                ShopifyError::Throttled
+            } else {
+                let body = response.text().await.unwrap_or_default();
+                ShopifyError::Status { status, body }
+            };
+            return Err((error, retry));
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err((ShopifyError::Status { status, body }, Retry::No));
+        }
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| (ShopifyError::Http(e), Retry::No))?;
+        let response: GraphQLResponse = serde_json::from_slice(&body)
+            .map_err(|e| (ShopifyError::Decode(e.to_string()), Retry::No))?;
+        if response.errors.is_empty() {
+            return Ok(response);
+        }
+        if is_throttled(&response.errors) {
+            let retry = response
+                .extensions
+                .as_ref()
+                .and_then(throttle_delay)
+                .map_or(Retry::Backoff, Retry::After);
+            return Err((ShopifyError::Throttled, retry));
+        }
+        Err((ShopifyError::GraphQL(response.errors), Retry::No))
+    }
+}
+
+/// The exponential backoff before retry number `attempt`, capped at `MAX_RETRY_DELAY`.
+fn backoff_delay(attempt: u32) -> Duration {
+    let factor = 2u32
+        .checked_pow(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    BASE_RETRY_DELAY.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+/// Reads a `Retry-After` header given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Whether Shopify refused to run the request because the store's query budget is spent.
+fn is_throttled(errors: &[GraphQLError]) -> bool {
+    errors.iter().any(|e| e.code() == Some("THROTTLED"))
+}
+
+/// How long until enough query cost is restored to run the throttled query again, from the
+/// `cost` extension: `{ requestedQueryCost, throttleStatus: { currentlyAvailable, restoreRate } }`.
+fn throttle_delay(extensions: &Value) -> Option<Duration> {
+    let cost = extensions.get("cost")?;
+    let requested = cost.get("requestedQueryCost")?.as_f64()?;
+    let throttle = cost.get("throttleStatus")?;
+    let available = throttle.get("currentlyAvailable")?.as_f64()?;
+    let restore_rate = throttle.get("restoreRate")?.as_f64()?;
+    if restore_rate <= 0.0 {
+        return None;
+    }
+    Duration::try_from_secs_f64((requested - available).max(0.0) / restore_rate).ok()
+}
+
+/// Gathers the `userErrors` of every mutation in `data`.
+fn collect_user_errors(data: &Value) -> Result<Vec<UserError>, ShopifyError> {
+    let mut errors = Vec::new();
+    let Some(fields) = data.as_object() else {
+        return Ok(errors);
+    };
+    for result in fields.values() {
+        if let Some(user_errors) = result.get("userErrors").filter(|v| !v.is_null()) {
+            let user_errors: Vec<UserError> = serde_json::from_value(user_errors.clone())
+                .map_err(|e| ShopifyError::Decode(format!("invalid userErrors: {}", e)))?;
+            errors.extend(user_errors);
+        }
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    fn errors(value: Value) -> Vec<GraphQLError> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_from_the_base_delay() {
+        assert_eq!(backoff_delay(1), Duration::from_millis(200));
+        assert_eq!(backoff_delay(2), Duration::from_millis(400));
+        assert_eq!(backoff_delay(4), Duration::from_millis(1600));
+    }
+
+    #[test]
+    fn backoff_is_capped_and_never_overflows() {
+        assert_eq!(backoff_delay(7), MAX_RETRY_DELAY);
+        for attempt in [32, 33, 64, u32::MAX] {
+            assert_eq!(backoff_delay(attempt), MAX_RETRY_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_after_reads_whole_and_fractional_seconds() {
+        assert_eq!(retry_after(&headers("2")), Some(Duration::from_secs(2)));
+        assert_eq!(
+            retry_after(&headers(" 0.5 ")),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn retry_after_ignores_missing_and_invalid_values() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&headers("-1")), None);
+        // HTTP dates are allowed by the spec but not sent by Shopify
+        assert_eq!(retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), None);
+    }
+
+    #[test]
+    fn throttled_is_detected_by_error_code() {
+        let throttled = errors(json!([
+            { "message": "Throttled", "extensions": { "code": "THROTTLED" } }
+        ]));
+        assert!(is_throttled(&throttled));
+
+        let other = errors(json!([
+            { "message": "Field 'x' doesn't exist", "extensions": { "code": "undefinedField" } },
+            { "message": "Throttled" }
+        ]));
+        assert!(!is_throttled(&other));
+    }
+
+    #[test]
+    fn throttle_delay_waits_for_the_missing_cost() {
+        let extensions = json!({
+            "cost": {
+                "requestedQueryCost": 10,
+                "throttleStatus": { "currentlyAvailable": 5, "restoreRate": 50 }
+            }
+        });
+        assert_eq!(
+            throttle_delay(&extensions),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn throttle_delay_needs_complete_cost_information() {
+        let available = json!({
+            "cost": {
+                "requestedQueryCost": 10,
+                "throttleStatus": { "currentlyAvailable": 20, "restoreRate": 50 }
+            }
+        });
+        assert_eq!(throttle_delay(&available), Some(Duration::ZERO));
+
+        let no_restore = json!({
+            "cost": {
+                "requestedQueryCost": 10,
+                "throttleStatus": { "currentlyAvailable": 0, "restoreRate": 0 }
+            }
+        });
+        assert_eq!(throttle_delay(&no_restore), None);
+        assert_eq!(
+            throttle_delay(&json!({ "cost": { "requestedQueryCost": 10 } })),
+            None
+        );
+        assert_eq!(throttle_delay(&json!({})), None);
+    }
+
+    #[test]
+    fn user_errors_are_collected_from_every_mutation() {
+        let data = json!({
+            "cartLinesAdd": {
+                "cart": null,
+                "userErrors": [
+                    { "field": ["lines", "0", "merchandiseId"], "message": "Unknown", "code": "INVALID" }
+                ]
+            },
+            "cartLinesRemove": {
+                "cart": null,
+                "userErrors": [{ "field": null, "message": "Gone" }]
+            }
+        });
+        let mut messages: Vec<_> = collect_user_errors(&data)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        messages.sort();
+        assert_eq!(messages, ["Gone", "Unknown"]);
+    }
+
+    #[test]
+    fn user_errors_absent_empty_or_null_are_none() {
+        assert!(
+            collect_user_errors(&json!({ "cart": { "id": "gid://shopify/Cart/1" } }))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            collect_user_errors(&json!({ "cartCreate": { "userErrors": [] } }))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            collect_user_errors(&json!({ "cartCreate": { "userErrors": null } }))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(collect_user_errors(&json!(null)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_user_errors_are_a_decode_error() {
+        let data = json!({ "cartCreate": { "userErrors": [{ "field": 3 }] } });
+        assert!(matches!(
+            collect_user_errors(&data),
+            Err(ShopifyError::Decode(_))
+        ));
+    }
+}
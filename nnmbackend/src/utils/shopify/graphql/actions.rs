@@ -1,19 +1,20 @@
-/// This module provides utility functions for interacting with Shopify's GraphQL API.
-///
-/// The functions in this module facilitate the construction and execution of GraphQL queries and mutations
-/// to interact with Shopify's backend services.
-///
-/// # Dependencies
-///
-/// - `std::collections::HashMap`: Utilized for storing and managing key-value pairs in the context of GraphQL queries.
-///
-/// # Errors
-///
-/// Functions in this module may return errors related to network issues, invalid GraphQL queries, or Shopify-specific errors.
+//! This module provides utility functions for interacting with Shopify's GraphQL API.
+//!
+//! The functions in this module facilitate the construction and execution of GraphQL queries and mutations
+//! to interact with Shopify's backend services. Request bodies are built as `serde_json` values,
+//! with variables serialized through `ShopifyGraphQLType`'s `Serialize` implementation.
+//!
+//! # Dependencies
+//!
+//! - `std::collections::HashMap`: Utilized for storing and managing key-value pairs in the context of GraphQL queries.
+//!
+//! # Errors
+//!
+//! Functions in this module may return errors related to network issues, invalid GraphQL queries, or Shopify-specific errors.
 
 use std::collections::HashMap;
 
-use super::{traits::GraphQLRepresentable, types::ShopifyGraphQLType};
+use super::{api::UserError, traits::GraphQLRepresentable, types::ShopifyGraphQLType};
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub enum GraphQLAction {
@@ -42,79 +43,27 @@ impl<T: GraphQLRepresentable> GraphQLQuery<T> {
     }
 
     pub fn query(qobj: T) -> Self {
-        Self {
-            action: GraphQLAction::Query,
-            query: qobj,
-            variables: HashMap::new(),
-        }
+        Self::new(GraphQLAction::Query, qobj, HashMap::new())
     }
 
     pub fn mutation(qobj: T, name: Option<String>) -> Self {
-        Self {
-            action: GraphQLAction::Mutation(name),
-            query: qobj,
-            variables: HashMap::new(),
-        }
+        Self::new(GraphQLAction::Mutation(name), qobj, HashMap::new())
     }
 
     pub fn add_variable(&mut self, key: String, value: ShopifyGraphQLType) {
         self.variables.insert(key, value);
     }
 
-    fn get_fmt_variables(&self) -> String {
-        // Format as JSON object
-        let mut s = String::new();
-        s.push('{');
-        if self.variables.is_empty() {
-            s.push('}');
-            return s;
-        }
-        for (key, value) in self.variables.iter() {
-            match value {
-                ShopifyGraphQLType::ID(v) => s.push_str(&format!("\"{}\": \"{}\", ", key, v)),
-                ShopifyGraphQLType::String(v) => s.push_str(&format!("\"{}\": \"{}\", ", key, v)),
-                ShopifyGraphQLType::Boolean(v) => s.push_str(&format!("\"{}\": {}, ", key, v)),
-                ShopifyGraphQLType::Int(v) => s.push_str(&format!("\"{}\": {}, ", key, v)),
-                ShopifyGraphQLType::Float(v) => s.push_str(&format!("\"{}\": {}, ", key, v)),
-                ShopifyGraphQLType::Json(v) => s.push_str(&format!("\"{}\": {}, ", key, v)),
-                ShopifyGraphQLType::Array(v) => {
-                    s.push_str(&format!("\"{}\": [", key));
-                    for item in v {
-                        s.push_str(&format!("{}, ", item.to_value_string()));
-                    }
-                    if s.ends_with(", ") {
-                        s.pop();
-                        s.pop();
-                    }
-                    s.push_str("], ");
-                }
-                ShopifyGraphQLType::Object(v) => s.push_str(&format!(
-                    "\"{}\": {}, ",
-                    key,
-                    v.iter()
-                        .map(|(k, v)| format!("\"{}\": {}", k, v.to_value_string()))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )), // Recursively format object
-                ShopifyGraphQLType::Custom(_, underlying) => {
-                    s.push_str(&format!("\"{}\": {}, ", key, underlying.to_value_string()))
-                }
-            }
-        }
-        if s.ends_with(", ") {
-            s.pop();
-            s.pop();
-        }
-        s.push('}');
-        s
-    }
-
-    pub fn to_payload(&self) -> String {
-        format!(
-            "{{\"query\":\"{}\", \"variables\": {}}}",
-            self.to_graphql(self.variables.clone()),
-            self.get_fmt_variables()
-        )
+    /// Builds the JSON request body: the query text and its variables. Both are serialized by
+    /// serde, so quotes, backslashes and newlines in either are escaped correctly. Fails if a
+    /// `Json` variable does not hold valid JSON.
+    pub fn to_payload(&self) -> Result<String, serde_json::Error> {
+        let variables = serde_json::to_value(&self.variables)?;
+        Ok(serde_json::json!({
+            "query": self.to_graphql(self.variables.clone()),
+            "variables": variables,
+        })
+        .to_string())
     }
 }
 
@@ -138,15 +87,12 @@ impl<T: GraphQLRepresentable> GraphQLQuery<T> {
             query.push(')');
         }
         query.push_str(" {\n");
-        if self.variables.contains_key(self.query.label().as_str()) {
-            let subargs = args.get(self.query.label().as_str()).unwrap();
-            query.push_str(
-                self.query
-                    .to_graphql(subargs.clone().to_object(&self.query.label()).clone())
-                    .as_str(),
-            );
-        } else {
-            query.push_str(self.query.to_graphql(args).as_str());
+        let label = self.query.label();
+        match args.get(label.as_str()) {
+            Some(subargs) if self.variables.contains_key(label.as_str()) => {
+                query.push_str(self.query.to_graphql(subargs.to_object(&label)).as_str());
+            }
+            _ => query.push_str(self.query.to_graphql(args).as_str()),
         }
         query.push_str("\n}");
         Some(query)
@@ -190,6 +136,13 @@ impl<T: GraphQLRepresentable> GraphQLQuery<T> {
 
         query.push_str(" {\n");
         query.push_str(self.query.to_graphql(HashMap::new()).as_str());
+        // Cart mutations report rejected input here rather than in the top-level errors
+        let user_errors = UserError::default();
+        query.push_str(&format!(
+            "\n{} {}",
+            user_errors.label(),
+            user_errors.to_graphql(HashMap::new())
+        ));
         query.push_str("\n}");
         query.push_str("\n}");
         Some(query)
@@ -217,9 +170,204 @@ impl<T: GraphQLRepresentable> GraphQLRepresentable for GraphQLQuery<T> {
     fn to_graphql(&self, args: HashMap<String, ShopifyGraphQLType>) -> String {
         match &self.action {
             GraphQLAction::Query => self.query_to_graphql(args).unwrap_or_default(),
-            GraphQLAction::Mutation(_) => {
-                self.mutation_to_graphql().unwrap_or_default()
+            GraphQLAction::Mutation(_) => self.mutation_to_graphql().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::utils::shopify::{
+        add_items_mutation, get_cart_query,
+        graphql::api::CartAPIRepresentation,
+        payloads::{
+            ItemPayload, LineRemovalPayload, LineUpdatePayload, MultiItemPayload,
+            MultiLineUpdatePayload,
+        },
+        remove_lines_mutation, update_lines_mutation,
+    };
+
+    /// A variable paired with the JSON it must serialize to.
+    fn variable() -> impl Strategy<Value = (ShopifyGraphQLType, Value)> {
+        let leaf = prop_oneof![
+            any::<String>().prop_map(|s| (ShopifyGraphQLType::ID(s.clone()), Value::String(s))),
+            any::<String>().prop_map(|s| (ShopifyGraphQLType::String(s.clone()), Value::String(s))),
+            any::<bool>().prop_map(|b| (ShopifyGraphQLType::Boolean(b), Value::Bool(b))),
+            any::<i64>().prop_map(|n| (ShopifyGraphQLType::Int(n), Value::from(n))),
+        ];
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(|items| {
+                    let (types, values) = items.into_iter().unzip();
+                    (ShopifyGraphQLType::Array(types), Value::Array(values))
+                }),
+                prop::collection::hash_map(any::<String>(), inner.clone(), 0..4).prop_map(
+                    |fields| {
+                        let values = fields
+                            .iter()
+                            .map(|(key, (_, value))| (key.clone(), value.clone()))
+                            .collect();
+                        let types = fields.into_iter().map(|(key, (ty, _))| (key, ty)).collect();
+                        (ShopifyGraphQLType::Object(types), Value::Object(values))
+                    }
+                ),
+                (any::<String>(), inner).prop_map(|(name, (ty, value))| {
+                    (ShopifyGraphQLType::Custom(name, Box::new(ty)), value)
+                }),
+            ]
+        })
+    }
+
+    fn parse(payload: &str) -> Value {
+        serde_json::from_str(payload).expect("payload is not valid JSON")
+    }
+
+    proptest! {
+        #[test]
+        fn variables_round_trip(
+            variables in prop::collection::hash_map(any::<String>(), variable(), 0..4)
+        ) {
+            let mut query = GraphQLQuery::mutation(
+                CartAPIRepresentation::default(),
+                Some("cartCreate".to_string()),
+            );
+            for (key, (ty, _)) in &variables {
+                query.add_variable(key.clone(), ty.clone());
+            }
+            let payload = parse(&query.to_payload().unwrap());
+
+            let text = payload["query"].as_str().expect("query is not a string");
+            prop_assert!(text.starts_with("mutation"));
+            for (key, (ty, _)) in &variables {
+                let declaration = format!("${}: {}", key, ty);
+                prop_assert!(text.contains(&declaration), "{:?} is not declared", declaration);
             }
+            let expected: serde_json::Map<String, Value> = variables
+                .into_iter()
+                .map(|(key, (_, value))| (key, value))
+                .collect();
+            prop_assert_eq!(&payload["variables"], &Value::Object(expected));
+        }
+
+        #[test]
+        fn cart_id_round_trips(id in any::<String>()) {
+            let payload = parse(&get_cart_query(&id).to_payload().unwrap());
+            prop_assert_eq!(
+                &payload["variables"]["id"],
+                &Value::String(format!("gid://shopify/Cart/{}", id))
+            );
+        }
+
+        #[test]
+        fn line_items_round_trip(
+            cart_id in any::<String>(),
+            lines in prop::collection::vec((any::<String>(), any::<u32>()), 0..4)
+        ) {
+            let items = MultiItemPayload {
+                items: lines
+                    .iter()
+                    .map(|(product_id, quantity)| ItemPayload {
+                        product_id: product_id.clone(),
+                        title: String::new(),
+                        handle: String::new(),
+                        description: String::new(),
+                        price: 0.0,
+                        currency: String::new(),
+                        quantity: *quantity,
+                    })
+                    .collect(),
+            };
+            let payload = parse(&add_items_mutation(&cart_id, &items).to_payload().unwrap());
+
+            prop_assert_eq!(
+                &payload["variables"]["cartId"],
+                &Value::String(format!("gid://shopify/Cart/{}", cart_id))
+            );
+            let expected: Vec<Value> = lines
+                .into_iter()
+                .map(|(product_id, quantity)| {
+                    serde_json::json!({ "merchandiseId": product_id, "quantity": quantity })
+                })
+                .collect();
+            prop_assert_eq!(&payload["variables"]["lines"], &Value::Array(expected));
+        }
+
+        #[test]
+        fn line_updates_round_trip(
+            cart_id in any::<String>(),
+            lines in prop::collection::vec((any::<String>(), any::<u32>()), 0..4)
+        ) {
+            let updates = MultiLineUpdatePayload {
+                items: lines
+                    .iter()
+                    .map(|(line_id, quantity)| LineUpdatePayload {
+                        line_id: line_id.clone(),
+                        quantity: *quantity,
+                    })
+                    .collect(),
+            };
+            let payload = parse(&update_lines_mutation(&cart_id, &updates).to_payload().unwrap());
+
+            let text = payload["query"].as_str().expect("query is not a string");
+            prop_assert!(text.contains("cartLinesUpdate"));
+            prop_assert_eq!(
+                &payload["variables"]["cartId"],
+                &Value::String(format!("gid://shopify/Cart/{}", cart_id))
+            );
+            let expected: Vec<Value> = lines
+                .into_iter()
+                .map(|(line_id, quantity)| {
+                    serde_json::json!({ "id": line_id, "quantity": quantity })
+                })
+                .collect();
+            prop_assert_eq!(&payload["variables"]["lines"], &Value::Array(expected));
+        }
+
+        #[test]
+        fn line_removals_round_trip(
+            cart_id in any::<String>(),
+            line_ids in prop::collection::vec(any::<String>(), 0..4)
+        ) {
+            let removals = LineRemovalPayload { line_ids: line_ids.clone() };
+            let payload = parse(&remove_lines_mutation(&cart_id, &removals).to_payload().unwrap());
+
+            let text = payload["query"].as_str().expect("query is not a string");
+            prop_assert!(text.contains("cartLinesRemove"));
+            prop_assert_eq!(
+                &payload["variables"]["cartId"],
+                &Value::String(format!("gid://shopify/Cart/{}", cart_id))
+            );
+            let expected: Vec<Value> = line_ids.into_iter().map(Value::String).collect();
+            prop_assert_eq!(&payload["variables"]["lineIds"], &Value::Array(expected));
         }
     }
+
+    #[test]
+    fn json_variables_are_embedded_as_json() {
+        let mut query = GraphQLQuery::query(CartAPIRepresentation::default());
+        query.add_variable(
+            "valid".to_string(),
+            ShopifyGraphQLType::Json(r#"{"a": [1, "two"]}"#.to_string()),
+        );
+        let payload = parse(&query.to_payload().unwrap());
+        assert_eq!(
+            payload["variables"]["valid"],
+            serde_json::json!({ "a": [1, "two"] })
+        );
+    }
+
+    #[test]
+    fn invalid_json_variables_are_an_error() {
+        let mut query = GraphQLQuery::query(CartAPIRepresentation::default());
+        query.add_variable(
+            "invalid".to_string(),
+            ShopifyGraphQLType::Json("not \"json\"".to_string()),
+        );
+        let error = query.to_payload().unwrap_err();
+        assert!(error.to_string().contains("invalid JSON variable"));
+    }
 }
@@ -1,20 +1,20 @@
-/// This module provides utility functions for interacting with the Shopify GraphQL API.
-///
-/// The utilities are designed to facilitate the construction and execution of GraphQL queries and mutations.
-///
-/// # Dependencies
-///
-/// - `std::collections::HashMap`: Used for storing key-value pairs in various utility functions.
-///
-/// # Errors
-///
-/// Functions in this module may return errors if there are issues with network requests or if the GraphQL API returns errors.
+//! This module provides utility functions for interacting with the Shopify GraphQL API.
+//!
+//! The utilities are designed to facilitate the construction and execution of GraphQL queries and mutations.
+//!
+//! # Dependencies
+//!
+//! - `std::collections::HashMap`: Used for storing key-value pairs in various utility functions.
+//!
+//! # Errors
+//!
+//! Functions in this module may return errors if there are issues with network requests or if the GraphQL API returns errors.
 
 use std::collections::HashMap;
 
 use super::{
     traits::GraphQLRepresentable,
-    types::{CostRepresentation, LineItem, MoneyV2, ShopifyGraphQLType},
+    types::{CostRepresentation, LineItem, MoneyV2, Product, ShopifyGraphQLType},
 };
 
 #[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
@@ -107,24 +107,83 @@ impl GraphQLRepresentable for CartAPIRepresentation {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct PageInfo {
+    #[serde(rename = "hasNextPage")]
+    pub has_next_page: bool,
+    #[serde(rename = "endCursor")]
+    pub end_cursor: Option<String>,
+}
+
+/// One page of the store's `products` connection.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct ProductsAPIRepresentation {
+    pub nodes: Vec<Product>,
+    #[serde(rename = "pageInfo")]
+    pub page_info: PageInfo,
+}
+
+impl GraphQLRepresentable for ProductsAPIRepresentation {
+    fn label(&self) -> String {
+        "products".to_string()
+    }
+    fn to_graphql(&self, args: HashMap<String, ShopifyGraphQLType>) -> String {
+        let mut s = String::from("products");
+        if !args.is_empty() {
+            let args = args
+                .keys()
+                .map(|key| format!("{}: ${}", key, key))
+                .collect::<Vec<_>>()
+                .join(", ");
+            s.push_str(&format!("({})", args));
+        }
+        s.push_str(&format!(
+            " {{\nnodes {}\npageInfo {{\n\thasNextPage\n\tendCursor\n}}\n}}",
+            Product::default().to_graphql(HashMap::new())
+        ));
+        s
+    }
+}
+
+/// An error Shopify reports inside a mutation's `userErrors`, e.g. an unknown merchandise id.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
 pub struct UserError {
-    pub field: String,
+    /// The path to the input field that caused the error, if the error is about a field.
+    pub field: Option<Vec<String>>,
     pub message: String,
+    pub code: Option<String>,
 }
 
 impl GraphQLRepresentable for UserError {
     fn label(&self) -> String {
-        "userError".to_string()
+        "userErrors".to_string()
     }
     fn to_graphql(&self, _: HashMap<String, ShopifyGraphQLType>) -> String {
-        "{ field\nmessage }".to_string()
+        "{ field\nmessage\ncode }".to_string()
     }
 }
 
+/// An entry of a GraphQL response's top-level `errors`.
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct GraphQLError {
     pub message: String,
-    pub path: Vec<String>,
-    pub locations: Vec<String>,
+    /// Field names and list indices leading to the failed field.
+    #[serde(default)]
+    pub path: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub locations: Vec<GraphQLErrorLocation>,
+    /// Shopify puts a machine-readable `code`, such as `THROTTLED`, here.
+    pub extensions: Option<serde_json::Value>,
+}
+
+impl GraphQLError {
+    pub fn code(&self) -> Option<&str> {
+        self.extensions.as_ref()?.get("code")?.as_str()
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct GraphQLErrorLocation {
+    pub line: u32,
+    pub column: u32,
 }
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use serde::ser::{Error, Serialize, SerializeMap, SerializeSeq, Serializer};
+
 use super::traits::GraphQLRepresentable;
 
 /// Represents various types that can be used in Shopify GraphQL queries and responses.
@@ -14,7 +16,7 @@ use super::traits::GraphQLRepresentable;
 /// - `Boolean(bool)`: Represents a GraphQL Boolean type.
 /// - `Int(i64)`: Represents a GraphQL Int type.
 /// - `Float(f64)`: Represents a GraphQL Float type.
-/// - `Json(String)`: Represents a JSON string.
+/// - `Json(String)`: Represents a value of the `JSON` scalar, given as JSON text.
 /// - `Array(Vec<ShopifyGraphQLType>)`: Represents a GraphQL list type.
 /// - `Object(HashMap<String, ShopifyGraphQLType>)`: Represents a GraphQL object type.
 /// - `Custom(String, Box<ShopifyGraphQLType>)`: Represents a custom GraphQL type with a name and an underlying type.
@@ -24,17 +26,21 @@ use super::traits::GraphQLRepresentable;
 /// - `to_object(&self, key: &str) -> HashMap<String, ShopifyGraphQLType>`:
 ///   Converts the enum variant to a `HashMap` with the given key if it's not already an object.
 ///
-/// - `to_value_string(&self) -> String`:
-///   Converts the enum variant to its corresponding GraphQL value string representation.
+/// # Serialization
+///
+/// `Serialize` writes the plain JSON value that goes in a request's `variables`, not the enum
+/// structure: `ID` and `String` become JSON strings, `Array` and `Object` become arrays and
+/// objects, and `Custom` is written as its underlying value. `Json` text is embedded as parsed
+/// JSON; text that does not parse is a serialization error. A non-finite `Float` becomes `null`.
 ///
 /// # Example
 ///
 /// ```
 /// let id = ShopifyGraphQLType::ID("123".to_string());
-/// let value_string = id.to_value_string();
-/// assert_eq!(value_string, "\"123\"");
+/// assert_eq!(serde_json::to_string(&id).unwrap(), "\"123\"");
 /// ```
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
 pub enum ShopifyGraphQLType {
     ID(String),
     String(String),
@@ -58,42 +64,36 @@ impl ShopifyGraphQLType {
             }
         }
     }
+}
 
-    pub fn to_value_string(&self) -> String {
+impl Serialize for ShopifyGraphQLType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         match self {
-            ShopifyGraphQLType::ID(v) => format!("\"{}\"", v),
-            ShopifyGraphQLType::String(v) => format!("\"{}\"", v),
-            ShopifyGraphQLType::Boolean(v) => format!("{}", v),
-            ShopifyGraphQLType::Int(v) => format!("{}", v),
-            ShopifyGraphQLType::Float(v) => format!("{}", v),
-            ShopifyGraphQLType::Json(v) => v.to_string(),
+            ShopifyGraphQLType::ID(v) | ShopifyGraphQLType::String(v) => {
+                serializer.serialize_str(v)
+            }
+            ShopifyGraphQLType::Boolean(v) => serializer.serialize_bool(*v),
+            ShopifyGraphQLType::Int(v) => serializer.serialize_i64(*v),
+            ShopifyGraphQLType::Float(v) => serializer.serialize_f64(*v),
+            ShopifyGraphQLType::Json(v) => match serde_json::from_str::<serde_json::Value>(v) {
+                Ok(value) => value.serialize(serializer),
+                Err(e) => Err(S::Error::custom(format!("invalid JSON variable: {}", e))),
+            },
             ShopifyGraphQLType::Array(v) => {
-                let mut s = String::new();
-                s.push('[');
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
                 for item in v {
-                    s.push_str(&format!("{}, ", item.to_value_string()));
-                }
-                if s.ends_with(", ") {
-                    s.pop();
-                    s.pop();
+                    seq.serialize_element(item)?;
                 }
-                s.push(']');
-                s
+                seq.end()
             }
             ShopifyGraphQLType::Object(v) => {
-                let mut s = String::new();
-                s.push('{');
-                for (key, value) in v.iter() {
-                    s.push_str(&format!("\"{}\": {}, ", key, value.to_value_string()));
-                }
-                if s.ends_with(", ") {
-                    s.pop();
-                    s.pop();
+                let mut map = serializer.serialize_map(Some(v.len()))?;
+                for (key, value) in v {
+                    map.serialize_entry(key, value)?;
                 }
-                s.push('}');
-                s
+                map.end()
             }
-            ShopifyGraphQLType::Custom(_, underlying) => underlying.to_value_string(),
+            ShopifyGraphQLType::Custom(_, underlying) => underlying.serialize(serializer),
         }
     }
 }
@@ -280,3 +280,144 @@ impl GraphQLRepresentable for LineItem {
         )
     }
 }
+
+/// This struct represents an image of a product or variant in Shopify's GraphQL API.
+/// The alt text and dimensions are optional, as Shopify does not always know them.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Image {
+    pub url: String,
+    #[serde(rename = "altText")]
+    pub alt_text: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl GraphQLRepresentable for Image {
+    fn label(&self) -> String {
+        "image".to_string()
+    }
+    fn to_graphql(&self, _: HashMap<String, ShopifyGraphQLType>) -> String {
+        "{\n\turl\n\taltText\n\twidth\n\theight\n}".to_string()
+    }
+}
+
+/// This struct represents one option a variant was chosen by, e.g. `Size: M`.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SelectedOption {
+    pub name: String,
+    pub value: String,
+}
+
+impl GraphQLRepresentable for SelectedOption {
+    fn label(&self) -> String {
+        "selectedOptions".to_string()
+    }
+    fn to_graphql(&self, _: HashMap<String, ShopifyGraphQLType>) -> String {
+        "{\n\tname\n\tvalue\n}".to_string()
+    }
+}
+
+/// This struct represents a purchasable variant of a product in Shopify's GraphQL API.
+/// Its `id` is the merchandise id used for cart lines. `compare_at_price` is set when the
+/// variant is on sale, and `image` when the variant has its own image.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductVariant {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "availableForSale")]
+    pub available_for_sale: bool,
+    pub price: MoneyV2,
+    #[serde(rename = "compareAtPrice")]
+    pub compare_at_price: Option<MoneyV2>,
+    #[serde(rename = "selectedOptions")]
+    pub selected_options: Vec<SelectedOption>,
+    pub image: Option<Image>,
+}
+
+impl GraphQLRepresentable for ProductVariant {
+    fn label(&self) -> String {
+        "variants".to_string()
+    }
+    fn to_graphql(&self, _: HashMap<String, ShopifyGraphQLType>) -> String {
+        format!(
+            "{{\nid\ntitle\navailableForSale\nprice {}\ncompareAtPrice {}\nselectedOptions {}\nimage {}\n}}",
+            MoneyV2::default().to_graphql(HashMap::new()),
+            MoneyV2::default().to_graphql(HashMap::new()),
+            SelectedOption::default().to_graphql(HashMap::new()),
+            Image::default().to_graphql(HashMap::new())
+        )
+    }
+}
+
+/// This struct represents the lowest and highest variant prices of a product.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct PriceRange {
+    #[serde(rename = "minVariantPrice")]
+    pub min_variant_price: MoneyV2,
+    #[serde(rename = "maxVariantPrice")]
+    pub max_variant_price: MoneyV2,
+}
+
+impl GraphQLRepresentable for PriceRange {
+    fn label(&self) -> String {
+        "priceRange".to_string()
+    }
+    fn to_graphql(&self, _: HashMap<String, ShopifyGraphQLType>) -> String {
+        format!(
+            "{{\n\tminVariantPrice {}\n\tmaxVariantPrice {}\n}}",
+            self.min_variant_price.to_graphql(HashMap::new()),
+            self.max_variant_price.to_graphql(HashMap::new())
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductImages {
+    pub nodes: Vec<Image>,
+}
+
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductVariants {
+    pub nodes: Vec<ProductVariant>,
+}
+
+/// This struct represents a product in Shopify's GraphQL API, with its images and variants.
+///
+/// Given a `handle` argument, `to_graphql` selects the product with `productByHandle`;
+/// otherwise it writes only the selection set, for use inside a `products` connection.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Product {
+    pub id: String,
+    pub handle: String,
+    pub title: String,
+    pub description: String,
+    #[serde(rename = "availableForSale")]
+    pub available_for_sale: bool,
+    #[serde(rename = "priceRange")]
+    pub price_range: PriceRange,
+    pub images: ProductImages,
+    pub variants: ProductVariants,
+}
+
+impl GraphQLRepresentable for Product {
+    fn label(&self) -> String {
+        "productByHandle".to_string()
+    }
+    fn to_graphql(&self, args: HashMap<String, ShopifyGraphQLType>) -> String {
+        let selection = format!(
+            "{{\nid\nhandle\ntitle\ndescription\navailableForSale\npriceRange {}\nimages(first: 10) {{\nnodes {}\n}}\nvariants(first: 100) {{\nnodes {}\n}}\n}}",
+            self.price_range.to_graphql(HashMap::new()),
+            Image::default().to_graphql(HashMap::new()),
+            ProductVariant::default().to_graphql(HashMap::new())
+        );
+        if args.is_empty() {
+            return selection;
+        }
+        let args = args
+            .keys()
+            .map(|key| format!("{}: ${}", key, key))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}({}) {}", self.label(), args, selection)
+    }
+}
@@ -0,0 +1,182 @@
+//! An in-process cache of Shopify product queries.
+//!
+//! `/products` and `/products/{handle}` read through a `ProductCache` so that a busy merch page
+//! does not turn every visit into a Storefront request. Each page of the listing (by page size
+//! and cursor) and each product (by handle) is kept for `cache.product_ttl_secs`, short enough
+//! that stock and price changes show up quickly. Unknown handles are cached too, so repeated
+//! requests for them do not reach Shopify either.
+//!
+//! Entries are fetched on demand: a read of a missing or expired entry queries Shopify, and
+//! concurrent misses for the same key may each do so. At most `MAX_CACHED_ENTRIES` entries are
+//! kept per map; expired ones are dropped to make room, and while the map is full of live
+//! entries new results are served without being cached.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::RwLock;
+
+use crate::{
+    error::ApiError,
+    utils::shopify::{
+        graphql::{api::ProductsAPIRepresentation, types::Product},
+        ShopifyClient,
+    },
+};
+
+use super::{get_product_query, get_products_query, ProductByHandleResponse, ProductsResponse};
+
+const MAX_CACHED_ENTRIES: usize = 256;
+
+struct Entry<V> {
+    value: V,
+    fetched_at: Instant,
+}
+
+/// A map whose entries are only returned while younger than its TTL.
+struct TtlMap<K, V> {
+    ttl: Duration,
+    entries: RwLock<HashMap<K, Entry<V>>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlMap<K, V> {
+    fn new(ttl: Duration) -> Self {
+        TtlMap {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    async fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.read().await;
+        let entry = entries.get(key)?;
+        (entry.fetched_at.elapsed() < self.ttl).then(|| entry.value.clone())
+    }
+
+    async fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.write().await;
+        if entries.len() >= MAX_CACHED_ENTRIES && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.fetched_at.elapsed() < self.ttl);
+            if entries.len() >= MAX_CACHED_ENTRIES {
+                return;
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                value,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+}
+
+pub struct ProductCache {
+    client: ShopifyClient,
+    pages: TtlMap<(usize, Option<String>), Arc<ProductsAPIRepresentation>>,
+    products: TtlMap<String, Option<Arc<Product>>>,
+}
+
+impl ProductCache {
+    pub fn new(client: ShopifyClient, ttl: Duration) -> Self {
+        ProductCache {
+            client,
+            pages: TtlMap::new(ttl),
+            products: TtlMap::new(ttl),
+        }
+    }
+
+    /// Returns the `first` products following the `after` cursor.
+    pub async fn products(
+        &self,
+        first: usize,
+        after: Option<String>,
+    ) -> Result<Arc<ProductsAPIRepresentation>, ApiError> {
+        let key = (first, after);
+        if let Some(page) = self.pages.get(&key).await {
+            return Ok(page);
+        }
+        let query = get_products_query(first, key.1.as_deref());
+        let response: ProductsResponse = self.client.execute(&query).await?;
+        let page = Arc::new(response.products);
+        self.pages.insert(key, page.clone()).await;
+        Ok(page)
+    }
+
+    /// Returns the product with the given handle, or `None` if the store has no such product.
+    pub async fn product(&self, handle: &str) -> Result<Option<Arc<Product>>, ApiError> {
+        let key = handle.to_string();
+        if let Some(product) = self.products.get(&key).await {
+            return Ok(product);
+        }
+        let response: ProductByHandleResponse =
+            self.client.execute(&get_product_query(handle)).await?;
+        let product = response.product.map(Arc::new);
+        self.products.insert(key, product.clone()).await;
+        Ok(product)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG_TTL: Duration = Duration::from_secs(60);
+
+    async fn len<K, V>(map: &TtlMap<K, V>) -> usize {
+        map.entries.read().await.len()
+    }
+
+    #[tokio::test]
+    async fn returns_live_entries() {
+        let map = TtlMap::new(LONG_TTL);
+        assert_eq!(map.get(&"a").await, None);
+        map.insert("a", 1).await;
+        assert_eq!(map.get(&"a").await, Some(1));
+        map.insert("a", 2).await;
+        assert_eq!(map.get(&"a").await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn hides_expired_entries() {
+        let map = TtlMap::new(Duration::from_millis(20));
+        map.insert("a", 1).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(map.get(&"a").await, None);
+
+        let map = TtlMap::new(Duration::ZERO);
+        map.insert("a", 1).await;
+        assert_eq!(map.get(&"a").await, None);
+    }
+
+    #[tokio::test]
+    async fn stops_caching_when_full_of_live_entries() {
+        let map = TtlMap::new(LONG_TTL);
+        for key in 0..MAX_CACHED_ENTRIES {
+            map.insert(key, key).await;
+        }
+        map.insert(MAX_CACHED_ENTRIES, 0).await;
+        assert_eq!(map.get(&MAX_CACHED_ENTRIES).await, None);
+        assert_eq!(len(&map).await, MAX_CACHED_ENTRIES);
+
+        // Keys already cached can still be refreshed
+        map.insert(0, 100).await;
+        assert_eq!(map.get(&0).await, Some(100));
+    }
+
+    #[tokio::test]
+    async fn drops_expired_entries_to_make_room() {
+        let map = TtlMap::new(Duration::from_millis(20));
+        for key in 0..MAX_CACHED_ENTRIES {
+            map.insert(key, key).await;
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        map.insert(MAX_CACHED_ENTRIES, 0).await;
+        assert_eq!(len(&map).await, 1);
+        assert_eq!(map.get(&MAX_CACHED_ENTRIES).await, Some(0));
+    }
+}
@@ -0,0 +1,226 @@
+//! This module provides queries for reading the store's product catalog from the Storefront API.
+//!
+//! # Structs
+//!
+//! - `ProductsResponse`: Represents the `data` of a `products` query, one page of products.
+//! - `ProductByHandleResponse`: Represents the `data` of a `productByHandle` query.
+//!
+//! # Functions
+//!
+//! - `get_products_query`: Constructs a query for a page of products, with their variants, prices,
+//!   availability and images.
+//! - `get_product_query`: Constructs a query for a single product by its handle.
+//!
+//! # Submodules
+//!
+//! - `cache`: Contains `ProductCache`, which keeps query results for `cache.product_ttl_secs`.
+//!
+//! # Example
+//!
+//! ```
+//! use nnmbackend::utils::shopify::products::{get_products_query, ProductsResponse};
+//!
+//! let query = get_products_query(20, None);
+//! // Execute the query with a `ShopifyClient`, e.g.
+//! // let page: ProductsResponse = client.execute(&query).await?;
+//! ```
+
+pub mod cache;
+
+use crate::utils::shopify::graphql::{
+    actions::GraphQLQuery,
+    api::ProductsAPIRepresentation,
+    types::{Product, ShopifyGraphQLType},
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductsResponse {
+    pub products: ProductsAPIRepresentation,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ProductByHandleResponse {
+    #[serde(rename = "productByHandle")]
+    pub product: Option<Product>,
+}
+
+/// Builds a query for the `first` products following the `after` cursor, or the first page
+/// without one.
+pub fn get_products_query(
+    first: usize,
+    after: Option<&str>,
+) -> GraphQLQuery<ProductsAPIRepresentation> {
+    let mut query = GraphQLQuery::query(ProductsAPIRepresentation::default());
+    query.add_variable("first".to_string(), ShopifyGraphQLType::Int(first as i64));
+    if let Some(after) = after {
+        query.add_variable(
+            "after".to_string(),
+            ShopifyGraphQLType::String(after.to_string()),
+        );
+    }
+    query
+}
+
+pub fn get_product_query(handle: &str) -> GraphQLQuery<Product> {
+    let mut query = GraphQLQuery::query(Product::default());
+    query.add_variable(
+        "handle".to_string(),
+        ShopifyGraphQLType::String(handle.to_string()),
+    );
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn parse(payload: &str) -> Value {
+        serde_json::from_str(payload).expect("payload is not valid JSON")
+    }
+
+    fn sorted_list(list: &str) -> Vec<&str> {
+        let mut items: Vec<_> = list.split(", ").collect();
+        items.sort();
+        items
+    }
+
+    /// A product as the Storefront API returns it for the selection set `Product` writes.
+    fn sample_product() -> Value {
+        json!({
+            "id": "gid://shopify/Product/1",
+            "handle": "nnm-tee",
+            "title": "NNM Tee",
+            "description": "A shirt.",
+            "availableForSale": true,
+            "priceRange": {
+                "minVariantPrice": { "amount": "20.0", "currencyCode": "USD" },
+                "maxVariantPrice": { "amount": "25.0", "currencyCode": "USD" }
+            },
+            "images": {
+                "nodes": [
+                    { "url": "https://cdn.shopify.com/tee.png", "altText": null, "width": 800, "height": 600 }
+                ]
+            },
+            "variants": {
+                "nodes": [
+                    {
+                        "id": "gid://shopify/ProductVariant/11",
+                        "title": "M",
+                        "availableForSale": true,
+                        "price": { "amount": "20.0", "currencyCode": "USD" },
+                        "compareAtPrice": null,
+                        "selectedOptions": [{ "name": "Size", "value": "M" }],
+                        "image": null
+                    },
+                    {
+                        "id": "gid://shopify/ProductVariant/12",
+                        "title": "XL",
+                        "availableForSale": false,
+                        "price": { "amount": "25.0", "currencyCode": "USD" },
+                        "compareAtPrice": { "amount": "30.0", "currencyCode": "USD" },
+                        "selectedOptions": [{ "name": "Size", "value": "XL" }],
+                        "image": { "url": "https://cdn.shopify.com/tee-xl.png", "altText": "XL", "width": null, "height": null }
+                    }
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn products_query_pages_with_first() {
+        let payload = parse(&get_products_query(20, None).to_payload().unwrap());
+        let query = payload["query"].as_str().unwrap();
+        assert!(query.starts_with("query ($first: Int!) {\nproducts(first: $first) {\nnodes {"));
+        assert!(query.contains("pageInfo {\n\thasNextPage\n\tendCursor\n}"));
+        assert!(!query.contains("after"));
+        assert_eq!(payload["variables"], json!({ "first": 20 }));
+    }
+
+    #[test]
+    fn products_query_resumes_after_a_cursor() {
+        let payload = parse(&get_products_query(5, Some("abc")).to_payload().unwrap());
+        let query = payload["query"].as_str().unwrap();
+        // Variables are declared, and passed as arguments, in no particular order
+        let (declarations, selection) = query.split_once(" {\nproducts(").unwrap();
+        let (arguments, _) = selection.split_once(") {\nnodes {").unwrap();
+        let declarations = declarations
+            .strip_prefix("query (")
+            .unwrap()
+            .strip_suffix(')');
+        assert_eq!(
+            sorted_list(declarations.unwrap()),
+            ["$after: String!", "$first: Int!"]
+        );
+        assert_eq!(sorted_list(arguments), ["after: $after", "first: $first"]);
+        assert_eq!(payload["variables"], json!({ "first": 5, "after": "abc" }));
+    }
+
+    #[test]
+    fn product_query_selects_by_handle() {
+        let payload = parse(&get_product_query("nnm-tee").to_payload().unwrap());
+        let query = payload["query"].as_str().unwrap();
+        assert!(query.starts_with(
+            "query ($handle: String!) {\nproductByHandle(handle: $handle) {\nid\nhandle\n"
+        ));
+        assert!(query.contains("variants(first: 100) {\nnodes {"));
+        assert!(query.contains("images(first: 10) {\nnodes {"));
+        assert_eq!(payload["variables"], json!({ "handle": "nnm-tee" }));
+    }
+
+    #[test]
+    fn products_response_deserializes() {
+        let data = json!({
+            "products": {
+                "nodes": [sample_product()],
+                "pageInfo": { "hasNextPage": true, "endCursor": "eyJsYXN0X2lkIjoxfQ==" }
+            }
+        });
+        let response: ProductsResponse = serde_json::from_value(data).unwrap();
+        let page = response.products;
+        assert!(page.page_info.has_next_page);
+        assert_eq!(
+            page.page_info.end_cursor.as_deref(),
+            Some("eyJsYXN0X2lkIjoxfQ==")
+        );
+
+        let product = &page.nodes[0];
+        assert_eq!(product.handle, "nnm-tee");
+        assert_eq!(product.price_range.max_variant_price.amount, "25.0");
+        assert_eq!(product.images.nodes[0].width, Some(800));
+        assert_eq!(product.images.nodes[0].alt_text, None);
+
+        let [medium, extra_large] = product.variants.nodes.as_slice() else {
+            panic!("expected two variants");
+        };
+        assert!(medium.compare_at_price.is_none() && medium.image.is_none());
+        assert!(!extra_large.available_for_sale);
+        assert_eq!(
+            extra_large.compare_at_price.as_ref().unwrap().amount,
+            "30.0"
+        );
+        assert_eq!(extra_large.selected_options[0].value, "XL");
+    }
+
+    #[test]
+    fn product_by_handle_response_deserializes() {
+        let found: ProductByHandleResponse =
+            serde_json::from_value(json!({ "productByHandle": sample_product() })).unwrap();
+        assert_eq!(found.product.unwrap().id, "gid://shopify/Product/1");
+
+        let missing: ProductByHandleResponse =
+            serde_json::from_value(json!({ "productByHandle": null })).unwrap();
+        assert!(missing.product.is_none());
+    }
+
+    #[test]
+    fn last_page_has_no_cursor() {
+        let data = json!({
+            "products": { "nodes": [], "pageInfo": { "hasNextPage": false, "endCursor": null } }
+        });
+        let response: ProductsResponse = serde_json::from_value(data).unwrap();
+        assert!(!response.products.page_info.has_next_page);
+        assert!(response.products.page_info.end_cursor.is_none());
+    }
+}
@@ -0,0 +1,275 @@
+//! A `BlobStore` backed by a directory on the local filesystem.
+//!
+//! Objects are stored as plain files beneath a root directory, using the object key as the
+//! relative path. Signed URLs point back at this backend's `/blob/{key}` route and carry an
+//! expiry timestamp plus an HMAC-SHA256 signature, so they behave like S3 presigned URLs. Each
+//! segment of the key is percent-encoded in the URL; the signature covers the decoded key.
+
+use std::{
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::Sha256;
+
+use super::{BlobInfo, BlobStore, StorageError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Everything but the unreserved characters of RFC 3986, so an encoded segment cannot be
+/// mistaken for a path separator, query or fragment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+    public_url: String,
+    secret: Vec<u8>,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: PathBuf, public_url: String, secret: Vec<u8>) -> Self {
+        LocalBlobStore {
+            root,
+            public_url: public_url.trim_end_matches('/').to_string(),
+            secret,
+        }
+    }
+
+    /// Resolves `key` to a path beneath the root, rejecting anything that could escape it.
+    fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn signature(&self, key: &str, expires: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(key.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Checks that a `/blob/{key}` request carries an unexpired signature produced by this store.
+    pub fn verify(&self, key: &str, expires: u64, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        if expires < unix_now() {
+            return false;
+        }
+        self.signature(key, expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+async fn collect_files(root: &Path) -> Result<Vec<BlobInfo>, StorageError> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(StorageError::Backend(e.into())),
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(anyhow::Error::from)? {
+            let metadata = entry.metadata().await.map_err(anyhow::Error::from)?;
+            let path = entry.path();
+            if metadata.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path
+                .strip_prefix(root)
+                .map_err(|e| anyhow!("{:?}", e))?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            found.push(BlobInfo {
+                key: relative,
+                size: metadata.len(),
+                last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+    }
+    Ok(found)
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>, StorageError> {
+        let mut objects = collect_files(&self.root).await?;
+        objects.retain(|obj| obj.key.starts_with(prefix));
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(anyhow::Error::from)?;
+        }
+        tokio::fs::copy(source, &path)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        let source = self.path_for(from)?;
+        let destination = self.path_for(to)?;
+        if let Some(parent) = destination.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(anyhow::Error::from)?;
+        }
+        match tokio::fs::copy(&source, &destination).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(from.to_string()))
+            }
+            Err(e) => Err(StorageError::Backend(e.into())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(StorageError::Backend(e.into())),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.path_for(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(e) => Err(StorageError::Backend(e.into())),
+        }
+    }
+
+    async fn signed_url(&self, key: &str, ttl: Duration) -> Result<String, StorageError> {
+        self.path_for(key)?;
+        let expires = unix_now() + ttl.as_secs();
+        let signature = hex::encode(self.signature(key, expires).finalize().into_bytes());
+        let path = key
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(format!(
+            "{}/blob/{}?expires={}&signature={}",
+            self.public_url, path, expires, signature
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test,
+        web::{self, Path, Query},
+        App, HttpResponse,
+    };
+
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Signed {
+        expires: u64,
+        signature: String,
+    }
+
+    fn store() -> LocalBlobStore {
+        LocalBlobStore::new(
+            std::env::temp_dir().join("nnm-local-store-tests"),
+            "http://localhost:3000/".to_string(),
+            b"secret".to_vec(),
+        )
+    }
+
+    #[actix_web::test]
+    async fn signed_url_encodes_each_segment() {
+        let url = store()
+            .signed_url("nnm_news/a b#1?x=%2F+é.png", Duration::from_secs(60))
+            .await
+            .unwrap();
+        let path = url.split_once('?').unwrap().0;
+        assert_eq!(
+            path,
+            "http://localhost:3000/blob/nnm_news/a%20b%231%3Fx%3D%252F%2B%C3%A9.png"
+        );
+    }
+
+    #[actix_web::test]
+    async fn signed_urls_verify_through_the_blob_route() {
+        let app =
+            test::init_service(
+                App::new().app_data(web::Data::new(store())).route(
+                    "/blob/{key:.*}",
+                    web::get().to(
+                        |store: web::Data<LocalBlobStore>,
+                         key: Path<String>,
+                         query: Query<Signed>| async move {
+                            if store.verify(&key, query.expires, &query.signature) {
+                                HttpResponse::Ok().body(key.into_inner())
+                            } else {
+                                HttpResponse::Forbidden().finish()
+                            }
+                        },
+                    ),
+                ),
+            )
+            .await;
+
+        for key in [
+            "nnm_issues/issue_1.pdf",
+            "nnm_news/a b.png",
+            "nnm_news/what?#now.png",
+            "nnm_news/100%+tax&more=yes.png",
+            "nnm_news/ümlaut/ünïcode.png",
+        ] {
+            let url = store()
+                .signed_url(key, Duration::from_secs(60))
+                .await
+                .unwrap();
+            let uri = url.strip_prefix("http://localhost:3000").unwrap();
+            let response =
+                test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert!(response.status().is_success(), "{} was rejected", key);
+            assert_eq!(test::read_body(response).await, key.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! This module provides a backend-agnostic interface for storing issue PDFs and news images.
+//!
+//! Routes talk to a `BlobStore` trait object rather than to a concrete S3 client, so the
+//! server can run against AWS in production and against the local filesystem in development
+//! and tests. The backend is chosen once at startup.
+//!
+//! # Traits
+//!
+//! - `BlobStore`: Lists, stores, copies, deletes, fetches and produces signed URLs for objects.
+//!
+//! # Implementations
+//!
+//! - `S3BlobStore` (in `utils::s3`): Stores objects in the regional S3 bucket.
+//! - `LocalBlobStore` (in `local`): Stores objects in a directory and serves signed URLs from the backend.
+//!
+//! `replicated` writes an object to every regional replica at once, rolling back on partial failure.
+//!
+//! # Functions
+//!
+//! - `get_issue_index`: Lists every issue PDF under `nnm_issues/`, sorted by issue number.
+//! - `get_issue_count`: Retrieves the number of the latest issue in an issue index.
+//! - `get_signed_url_for_latest_issue`: Generates a signed URL for the latest issue in an issue index.
+//! - `get_signed_url_for_issue`: Generates a signed URL for a specific issue based on the issue number.
+//!
+//! # Errors
+//!
+//! All operations return a `StorageError`, which distinguishes missing objects from backend failures.
+
+pub mod local;
+pub mod replicated;
+
+use std::{path::Path, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+/// The prefix every issue PDF is stored under.
+pub const ISSUE_PREFIX: &str = "nnm_issues/";
+
+/// The prefix uploads are staged under until their metadata is written. Nothing lists it.
+pub const PENDING_PREFIX: &str = "nnm_pending/";
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Object not found: {0}")]
+    NotFound(String),
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Storage backend error: {0}")]
+    Backend(#[from] anyhow::Error),
+}
+
+/// Metadata about a single stored object, as returned by `BlobStore::list`.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct BlobInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// An issue PDF found in the store.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct IssueObject {
+    pub number: usize,
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Lists every object whose key starts with `prefix`, following pagination to the end.
+    async fn list(&self, prefix: &str) -> Result<Vec<BlobInfo>, StorageError>;
+
+    /// Stores the file at `path` under `key`, replacing any existing object, without reading
+    /// it into memory all at once.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), StorageError>;
+
+    /// Copies the object stored under `from` to `to`, replacing any existing object at `to`.
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError>;
+
+    /// Removes the object stored under `key`. Deleting a missing object is not an error.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Fetches the full contents of the object stored under `key`.
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Produces a URL that grants read access to `key` for `ttl`.
+    async fn signed_url(&self, key: &str, ttl: Duration) -> Result<String, StorageError>;
+}
+
+pub fn issue_key(issue_number: usize) -> String {
+    format!("{}issue_{}.pdf", ISSUE_PREFIX, issue_number)
+}
+
+/// A unique staging key for an upload of `issue_number`, so concurrent uploads do not collide.
+pub fn pending_issue_key(issue_number: usize) -> String {
+    format!(
+        "{}issue_{}_{}.pdf",
+        PENDING_PREFIX,
+        issue_number,
+        hex::encode(rand::random::<[u8; 8]>())
+    )
+}
+
+/// Extracts the issue number from a key such as `nnm_issues/issue_12.pdf`.
+///
+/// Returns `None` for objects that are not issue PDFs. PDFs whose name does not follow the
+/// `issue_{n}.pdf` pattern are skipped with a warning rather than failing the whole listing.
+fn parse_issue_number(key: &str) -> Option<usize> {
+    // check if key is a pdf
+    let stem = key.strip_suffix(".pdf")?;
+    let name = stem.rsplit('/').next().unwrap_or(stem);
+    let number = name
+        .strip_prefix("issue_")
+        .and_then(|number| number.parse::<usize>().ok());
+    if number.is_none() {
+        log::warn!("Skipping malformed issue key {:?}", key);
+    }
+    number
+}
+
+/// Lists every issue PDF in the store, sorted by ascending issue number.
+pub async fn get_issue_index(store: &dyn BlobStore) -> Result<Vec<IssueObject>, StorageError> {
+    let mut issues: Vec<IssueObject> = store
+        .list(ISSUE_PREFIX)
+        .await?
+        .into_iter()
+        .filter_map(|obj| {
+            Some(IssueObject {
+                number: parse_issue_number(&obj.key)?,
+                key: obj.key,
+                size: obj.size,
+                last_modified: obj.last_modified,
+            })
+        })
+        .collect();
+    issues.sort_by_key(|issue| issue.number);
+    issues.dedup_by_key(|issue| issue.number);
+    Ok(issues)
+}
+
+fn latest_issue(index: &[IssueObject]) -> Result<&IssueObject, StorageError> {
+    index.last().ok_or(StorageError::NotFound(
+        "No issues have been published".to_string(),
+    ))
+}
+
+pub fn get_issue_count(index: &[IssueObject]) -> Result<usize, StorageError> {
+    Ok(latest_issue(index)?.number)
+}
+
+pub async fn get_signed_url_for_latest_issue(
+    index: &[IssueObject],
+    store: &dyn BlobStore,
+    ttl: Duration,
+) -> Result<String, StorageError> {
+    // The index lists every issue, since some numbers may be missing
+    store.signed_url(&latest_issue(index)?.key, ttl).await
+}
+
+pub async fn get_signed_url_for_issue(
+    issue_number: usize,
+    store: &dyn BlobStore,
+    ttl: Duration,
+) -> Result<String, StorageError> {
+    store.signed_url(&issue_key(issue_number), ttl).await
+}
@@ -0,0 +1,326 @@
+//! Writes objects to every regional replica of the blob store.
+//!
+//! Readers are served from the bucket in their own region, so an issue must land in every
+//! configured bucket or in none of them. `put_to_all_replicas` and `copy_in_all_replicas`
+//! write to all replicas concurrently; if any write fails, the copies that did succeed are
+//! deleted again, and any replica that could not be cleaned up is flagged in the returned
+//! report and the log. `delete_from_all_replicas` is a best-effort cleanup.
+
+use std::{path::Path, sync::Arc};
+
+use futures::future::join_all;
+
+use super::{BlobStore, StorageError};
+
+/// One copy of the blob store, named after the region it serves.
+#[derive(Clone)]
+pub struct Replica {
+    pub region: String,
+    pub store: Arc<dyn BlobStore>,
+}
+
+/// The outcome of a replicated write for a single region.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ReplicaStatus {
+    pub region: String,
+    /// Whether the object is present in this region once the write has finished.
+    pub stored: bool,
+    /// Whether a successful write had to be deleted again because another region failed.
+    pub rolled_back: bool,
+    pub error: Option<String>,
+}
+
+/// The per-region outcome of `put_to_all_replicas` or `copy_in_all_replicas`.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ReplicationReport {
+    pub key: String,
+    pub replicas: Vec<ReplicaStatus>,
+}
+
+impl ReplicationReport {
+    /// True if every replica holds the object.
+    pub fn is_complete(&self) -> bool {
+        self.replicas.iter().all(|replica| replica.stored)
+    }
+
+    /// True if replicas disagree about whether the object exists, because a rollback failed.
+    pub fn is_inconsistent(&self) -> bool {
+        !self.is_complete() && self.replicas.iter().any(|replica| replica.stored)
+    }
+}
+
+/// Stores the file at `path` under `key` in every replica, or in none of them.
+///
+/// Check `ReplicationReport::is_complete` on the result: a partial failure is reported, not
+/// returned as an error, so the caller can show the client which regions failed.
+pub async fn put_to_all_replicas(
+    replicas: &[Replica],
+    key: &str,
+    path: &Path,
+) -> ReplicationReport {
+    let results = join_all(
+        replicas
+            .iter()
+            .map(|replica| replica.store.put_file(key, path)),
+    )
+    .await;
+    finish(replicas, key, results, true).await
+}
+
+/// Copies `from` to `to` within every replica.
+///
+/// With `rollback`, a partial failure deletes `to` again wherever the copy succeeded. Pass
+/// `false` when `to` held an object before, since deleting it would lose that object too.
+pub async fn copy_in_all_replicas(
+    replicas: &[Replica],
+    from: &str,
+    to: &str,
+    rollback: bool,
+) -> ReplicationReport {
+    let results = join_all(replicas.iter().map(|replica| replica.store.copy(from, to))).await;
+    finish(replicas, to, results, rollback).await
+}
+
+/// Deletes `key` from every replica, logging any replica where that fails.
+///
+/// Deletes are not rolled back; on failure the report marks the regions that still hold `key`.
+pub async fn delete_from_all_replicas(
+    replicas: &[Replica],
+    key: &str,
+) -> Result<(), ReplicationReport> {
+    let results = join_all(replicas.iter().map(|replica| replica.store.delete(key))).await;
+    let statuses: Vec<ReplicaStatus> = replicas
+        .iter()
+        .zip(results)
+        .map(|(replica, result)| {
+            if let Err(e) = &result {
+                log::warn!("Could not delete {} in {}: {}", key, replica.region, e);
+            }
+            ReplicaStatus {
+                region: replica.region.clone(),
+                stored: result.is_err(),
+                rolled_back: false,
+                error: result.err().map(|e| e.to_string()),
+            }
+        })
+        .collect();
+    if statuses.iter().any(|status| status.stored) {
+        return Err(ReplicationReport {
+            key: key.to_string(),
+            replicas: statuses,
+        });
+    }
+    Ok(())
+}
+
+/// Builds the report for a replicated write, rolling back successful writes if any failed.
+async fn finish(
+    replicas: &[Replica],
+    key: &str,
+    results: Vec<Result<(), StorageError>>,
+    rollback: bool,
+) -> ReplicationReport {
+    let mut statuses: Vec<ReplicaStatus> = replicas
+        .iter()
+        .zip(results)
+        .map(|(replica, result)| ReplicaStatus {
+            region: replica.region.clone(),
+            stored: result.is_ok(),
+            rolled_back: false,
+            error: result.err().map(|e| e.to_string()),
+        })
+        .collect();
+
+    if statuses.iter().all(|status| status.stored) {
+        return ReplicationReport {
+            key: key.to_string(),
+            replicas: statuses,
+        };
+    }
+
+    // Undo the writes that succeeded so no region serves an issue the others lack
+    for (replica, status) in replicas.iter().zip(statuses.iter_mut()) {
+        if !status.stored {
+            log::warn!(
+                "Could not store {} in {}: {}",
+                key,
+                status.region,
+                status.error.as_deref().unwrap_or_default()
+            );
+            continue;
+        }
+        if !rollback {
+            log::error!(
+                "Stored {} in {} but not every region; replicas are inconsistent",
+                key,
+                status.region
+            );
+            continue;
+        }
+        match replica.store.delete(key).await {
+            Ok(()) => {
+                status.stored = false;
+                status.rolled_back = true;
+            }
+            Err(e) => {
+                log::error!(
+                    "Could not roll back {} in {}; replicas are inconsistent: {}",
+                    key,
+                    status.region,
+                    e
+                );
+                status.error = Some(format!("Rollback failed: {}", e));
+            }
+        }
+    }
+
+    ReplicationReport {
+        key: key.to_string(),
+        replicas: statuses,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::testing::{local_store, Faults, FaultyStore};
+
+    const KEY: &str = "nnm_issues/issue_1.pdf";
+
+    /// One replica per entry of `faults`, each in its own directory, named `region-{n}`.
+    fn regions(faults: &[Faults]) -> (Vec<TempDir>, Vec<Replica>) {
+        faults
+            .iter()
+            .enumerate()
+            .map(|(n, faults)| {
+                let root = tempfile::tempdir().unwrap();
+                let store = FaultyStore::new(local_store(root.path()), *faults);
+                let replica = Replica {
+                    region: format!("region-{}", n),
+                    store: Arc::new(store),
+                };
+                (root, replica)
+            })
+            .unzip()
+    }
+
+    fn source() -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), b"%PDF-1.5").unwrap();
+        file
+    }
+
+    /// Whether each replica holds `key`, checked without going through any injected faults.
+    fn held(roots: &[TempDir], key: &str) -> Vec<bool> {
+        roots
+            .iter()
+            .map(|root| root.path().join(key).exists())
+            .collect()
+    }
+
+    fn put_failure() -> Faults {
+        Faults {
+            put: true,
+            ..Faults::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_in_every_replica() {
+        let (roots, replicas) = regions(&[Faults::default(), Faults::default()]);
+        let report = put_to_all_replicas(&replicas, KEY, source().path()).await;
+        assert!(report.is_complete());
+        assert!(!report.is_inconsistent());
+        assert_eq!(report.key, KEY);
+        for status in &report.replicas {
+            assert!(status.stored);
+            assert!(!status.rolled_back);
+            assert_eq!(status.error, None);
+        }
+        assert_eq!(held(&roots, KEY), [true, true]);
+    }
+
+    #[tokio::test]
+    async fn rolls_back_successful_replicas_when_one_fails() {
+        let (roots, replicas) = regions(&[Faults::default(), put_failure(), Faults::default()]);
+        let report = put_to_all_replicas(&replicas, KEY, source().path()).await;
+        assert!(!report.is_complete());
+        assert!(!report.is_inconsistent());
+
+        let rolled_back: Vec<_> = report.replicas.iter().map(|s| s.rolled_back).collect();
+        assert_eq!(rolled_back, [true, false, true]);
+        assert!(report.replicas.iter().all(|status| !status.stored));
+        assert_eq!(report.replicas[1].region, "region-1");
+        assert!(report.replicas[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("injected put failure"));
+        assert_eq!(held(&roots, KEY), [false, false, false]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_rollback_is_reported_as_inconsistent() {
+        let undeletable = Faults {
+            delete: true,
+            ..Faults::default()
+        };
+        let (roots, replicas) = regions(&[undeletable, put_failure()]);
+        let report = put_to_all_replicas(&replicas, KEY, source().path()).await;
+        assert!(!report.is_complete());
+        assert!(report.is_inconsistent());
+
+        let stuck = &report.replicas[0];
+        assert!(stuck.stored);
+        assert!(!stuck.rolled_back);
+        assert!(stuck
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("Rollback failed"));
+        assert_eq!(held(&roots, KEY), [true, false]);
+    }
+
+    #[tokio::test]
+    async fn copies_without_rollback_keep_what_was_copied() {
+        let copy_failure = Faults {
+            copy: true,
+            ..Faults::default()
+        };
+        let (roots, replicas) = regions(&[Faults::default(), copy_failure]);
+        let staged = put_to_all_replicas(&replicas, "nnm_pending/a.pdf", source().path()).await;
+        assert!(staged.is_complete());
+
+        // With rollback the successful copy is deleted again
+        let report = copy_in_all_replicas(&replicas, "nnm_pending/a.pdf", KEY, true).await;
+        assert!(!report.is_inconsistent());
+        assert_eq!(held(&roots, KEY), [false, false]);
+
+        // Without it, the copy stays and the report says the regions disagree
+        let report = copy_in_all_replicas(&replicas, "nnm_pending/a.pdf", KEY, false).await;
+        assert!(report.is_inconsistent());
+        assert!(report.replicas[0].stored);
+        assert!(!report.replicas[0].rolled_back);
+        assert_eq!(held(&roots, KEY), [true, false]);
+    }
+
+    #[tokio::test]
+    async fn reports_replicas_that_could_not_be_deleted() {
+        let undeletable = Faults {
+            delete: true,
+            ..Faults::default()
+        };
+        let (roots, replicas) = regions(&[Faults::default(), undeletable]);
+        put_to_all_replicas(&replicas, KEY, source().path()).await;
+
+        let report = delete_from_all_replicas(&replicas, KEY).await.unwrap_err();
+        let stored: Vec<_> = report.replicas.iter().map(|s| s.stored).collect();
+        assert_eq!(stored, [false, true]);
+        assert_eq!(held(&roots, KEY), [false, true]);
+
+        let (_roots, replicas) = regions(&[Faults::default()]);
+        assert!(delete_from_all_replicas(&replicas, KEY).await.is_ok());
+    }
+}